@@ -12,9 +12,17 @@
 //! # <Load the index.html with your WASM in your browser...>
 //! ```
 
-use clap::{Args, Parser};
-use std::{fs, path::PathBuf};
-use wasm2map::WASM;
+use clap::{ArgGroup, Args, Parser, Subcommand};
+use std::{
+    fs,
+    io::{self, Read},
+    net::TcpListener,
+    path::{Path, PathBuf},
+};
+use wasm2map::{
+    ColumnMode, Compression, Coverage, GitRevision, LoadOptions, MapOptions, SourceKind,
+    StaleSources, SymbolServer, WASM,
+};
 
 // Cargo commands receive the name of the subcommand as the main command
 // so we need to consume the name of our executable in order to get to the
@@ -23,15 +31,57 @@ use wasm2map::WASM;
 #[command(name = "cargo")]
 #[command(bin_name = "cargo")]
 enum CargoCli {
-    Wasm2map(WasmFile),
+    Wasm2map(Wasm2map),
 }
 
+// Without a subcommand the sourcemap is generated for the WASM file, the
+// subcommands offer additional tools to work with the debug info
 #[derive(Args)]
 #[command(author, version, about, long_about = None)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+struct Wasm2map {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    map: WasmFile,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    #[command(about = "Move the DWARF debug info to a separate file and link it from the WASM")]
+    Split(SplitArgs),
+    #[command(about = "Show how much of the code each crate, source file or function takes up")]
+    Size(SizeArgs),
+    #[command(about = "Show how well the DWARF debug info covers the code")]
+    Report(ReportArgs),
+    #[command(
+        about = "Resolve the WASM frames of a browser or wasmtime stack trace to Rust source"
+    )]
+    Symbolicate(SymbolicateArgs),
+    #[command(
+        about = "Archive the WASM, its debug info, sourcemap and sources to symbolicate it later"
+    )]
+    Bundle(Box<BundleArgs>),
+    #[command(about = "Answer HTTP/JSON requests to symbolicate the WASM offsets of the builds")]
+    ServeSymbols(ServeSymbolsArgs),
+    #[command(
+        about = "Show the sections, sourceMappingURL, producers and DWARF units of the WASM"
+    )]
+    Info(InfoArgs),
+}
+
+// The sourcemap is either linked from the WASM (--patch) or inlined in it
+// (--inline), the options only making sense then require either
+#[derive(Args)]
+#[command(group(ArgGroup::new("link").args(["patch", "inline"])))]
 struct WasmFile {
     // The first argument is the WASM file path to process
-    #[arg(help = "The path to the WASM file with debug info embedded (DWARF)")]
-    path: PathBuf,
+    #[arg(
+        required = true,
+        help = "The path to the WASM file with debug info embedded (DWARF)"
+    )]
+    path: Option<PathBuf>,
 
     #[arg(
         short,
@@ -40,6 +90,20 @@ struct WasmFile {
     )]
     map_path: Option<PathBuf>,
 
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        help = "Also write the sourcemap compressed next to it (i.e. <map>.gz): gzip"
+    )]
+    compress: Option<Compression>,
+
+    #[arg(
+        long,
+        requires = "compress",
+        help = "Only write the compressed sourcemap, not the plain one"
+    )]
+    compressed_only: bool,
+
     #[arg(
         short,
         long,
@@ -56,26 +120,836 @@ struct WasmFile {
     )]
     base_url: Option<String>,
 
+    #[arg(
+        long,
+        conflicts_with_all = ["patch", "map_path", "compress"],
+        help = "Inline the sourcemap in the WASM as a data URL instead of writing a separate file"
+    )]
+    inline: bool,
+
+    #[arg(
+        long,
+        help = "Also store the sourcemap JSON in a sourceMap custom section of the WASM"
+    )]
+    embed: bool,
+
     #[arg(
         long,
         requires = "patch",
-        help = "Bundle sourcefiles into the sourcemap"
+        help = "Add a build_id section with the debug ID of the sourcemap to the WASM"
+    )]
+    build_id: bool,
+
+    #[arg(
+        long,
+        requires = "link",
+        help = "Bundle sourcefiles into the sourcemap (with --patch or --inline)"
     )]
     bundle_sources: bool,
+
+    #[arg(
+        long,
+        requires = "bundle_sources",
+        value_name = "POLICY",
+        help = "What to do with sourcefiles changed since the build: ignore, skip, warn (default) or fail"
+    )]
+    stale_sources: Option<StaleSources>,
+
+    #[arg(
+        long,
+        requires = "bundle_sources",
+        value_name = "REV",
+        help = "Bundle the sourcefiles as they are in this commit of the git repository in the current directory"
+    )]
+    sources_from_git: Option<String>,
+
+    #[command(flatten)]
+    options: MapArgs,
+
+    #[arg(
+        short,
+        long,
+        help = "Read the DWARF debug info from this separate file (i.e. myproject.debug.wasm)"
+    )]
+    debug_file: Option<PathBuf>,
+
+    #[arg(
+        long,
+        conflicts_with = "debug_file",
+        help = "Move the DWARF debug info to <name>.debug.wasm after generating the sourcemap"
+    )]
+    split: bool,
+
+    #[arg(
+        long,
+        requires = "split",
+        help = "URL of the debug info file referenced from the WASM (default: its filename)"
+    )]
+    debug_url: Option<String>,
+
+    #[arg(
+        long,
+        conflicts_with = "split",
+        help = "Remove the DWARF debug info from the WASM after generating the sourcemap"
+    )]
+    strip: bool,
+
+    #[arg(
+        long,
+        requires = "strip",
+        help = "Also remove the function names (name section) from the WASM"
+    )]
+    strip_names: bool,
+}
+
+// The sourcemap options shared by the commands generating one
+#[derive(Args)]
+struct MapArgs {
+    #[arg(
+        long,
+        value_name = "MODE",
+        help = "Translate the DWARF columns to UTF-16 from raw (default), bytes or chars"
+    )]
+    columns: Option<ColumnMode>,
+
+    #[arg(
+        long,
+        help = "Generate a reproducible sourcemap, only bundling sourcefiles embedded in the DWARF info"
+    )]
+    canonical: bool,
+
+    #[arg(
+        long,
+        value_name = "GLOB",
+        help = "Only map sourcefiles matching the glob (i.e. '**/myapp/src/**'), can be repeated"
+    )]
+    include: Vec<String>,
+
+    #[arg(
+        long,
+        value_name = "GLOB",
+        help = "Don't map sourcefiles matching the glob, can be repeated"
+    )]
+    exclude: Vec<String>,
+
+    #[arg(
+        long = "crate",
+        value_name = "NAME",
+        help = "Only map sourcefiles of the crate, can be repeated"
+    )]
+    crates: Vec<String>,
+
+    #[arg(
+        long = "exclude-crate",
+        value_name = "NAME",
+        help = "Don't map sourcefiles of the crate, can be repeated"
+    )]
+    exclude_crates: Vec<String>,
+
+    #[arg(
+        long = "kind",
+        value_name = "KIND",
+        help = "Only map sourcefiles of the kind: workspace, registry, git, std or other, can be repeated"
+    )]
+    kinds: Vec<SourceKind>,
+
+    #[arg(
+        long,
+        value_name = "URL",
+        help = "The sourceRoot of the sourcemap, prepended to the source URLs by the browser"
+    )]
+    source_root: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "FROM=TO",
+        value_parser = parse_remap,
+        help = "Replace the FROM prefix of the source paths with TO, can be repeated"
+    )]
+    remap_path_prefix: Vec<(PathBuf, PathBuf)>,
+
+    #[arg(
+        long,
+        value_name = "GLOB",
+        help = "Put the sourcefiles matching the glob on the ignoreList of the sourcemap, can be repeated"
+    )]
+    ignore: Vec<String>,
+
+    #[arg(
+        long,
+        value_name = "KIND",
+        help = "Put the sourcefiles of the kind on the ignoreList of the sourcemap, can be repeated"
+    )]
+    ignore_kind: Vec<SourceKind>,
+
+    #[arg(long, help = "Indent the sourcemap JSON")]
+    pretty: bool,
+
+    #[arg(long, help = "Add the function names to the sourcemap")]
+    names: bool,
+}
+
+#[derive(Args)]
+struct SplitArgs {
+    #[arg(help = "The path to the WASM file with debug info embedded (DWARF)")]
+    path: PathBuf,
+
+    #[arg(
+        short,
+        long,
+        help = "Override default debug info file path (default: <name>.debug.wasm)"
+    )]
+    debug_file: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "URL of the debug info file referenced from the WASM (default: its filename)"
+    )]
+    debug_url: Option<String>,
+}
+
+#[derive(Args)]
+struct SizeArgs {
+    #[arg(help = "The path to the WASM file with debug info embedded (DWARF)")]
+    path: PathBuf,
+
+    #[arg(
+        short,
+        long,
+        help = "Read the debug info from a separate file instead of the WASM"
+    )]
+    debug_file: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_parser = ["crate", "file", "function"],
+        default_value = "crate",
+        help = "What to break the code size down by"
+    )]
+    by: String,
+
+    #[arg(
+        short = 'n',
+        long,
+        value_name = "N",
+        help = "Only show the N largest entries"
+    )]
+    top: Option<usize>,
+
+    #[arg(
+        long,
+        conflicts_with_all = ["by", "top"],
+        help = "Print the whole report as JSON"
+    )]
+    json: bool,
+}
+
+#[derive(Args)]
+struct ReportArgs {
+    #[arg(help = "The path to the WASM file with debug info embedded (DWARF)")]
+    path: PathBuf,
+
+    #[arg(
+        short,
+        long,
+        help = "Read the debug info from a separate file instead of the WASM"
+    )]
+    debug_file: Option<PathBuf>,
+
+    #[arg(
+        short = 'n',
+        long,
+        value_name = "N",
+        help = "Only show the N least covered functions (default: 20)"
+    )]
+    top: Option<usize>,
+
+    #[arg(
+        long,
+        help = "Check the sourcefiles of a reproducible sourcemap, only the ones embedded in the DWARF info"
+    )]
+    canonical: bool,
+
+    #[arg(
+        long,
+        conflicts_with = "canonical",
+        value_name = "REV",
+        help = "Check the sourcefiles as they are in this commit of the git repository in the current directory"
+    )]
+    sources_from_git: Option<String>,
+
+    #[arg(long, conflicts_with = "top", help = "Print the whole report as JSON")]
+    json: bool,
+}
+
+#[derive(Args)]
+struct InfoArgs {
+    #[arg(help = "The path to the WASM file")]
+    path: PathBuf,
+
+    #[arg(
+        short,
+        long,
+        help = "Read the debug info from a separate file instead of the WASM"
+    )]
+    debug_file: Option<PathBuf>,
+}
+
+#[derive(Args)]
+struct SymbolicateArgs {
+    #[arg(help = "The path to the WASM file with debug info embedded (DWARF)")]
+    path: PathBuf,
+
+    #[arg(help = "The file with the stack trace (default: read it from stdin)")]
+    trace: Option<PathBuf>,
+
+    #[arg(
+        short,
+        long,
+        help = "Read the debug info from a separate file instead of the WASM"
+    )]
+    debug_file: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "ID",
+        help = "Refuse to symbolicate unless the WASM has this debug ID"
+    )]
+    debug_id: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Refuse to symbolicate unless the sourcemap was generated from the WASM"
+    )]
+    map: Option<PathBuf>,
+}
+
+#[derive(Args)]
+struct BundleArgs {
+    #[arg(help = "The path to the WASM file with debug info embedded (DWARF)")]
+    path: PathBuf,
+
+    #[arg(
+        short,
+        long,
+        help = "Read the debug info from a separate file instead of the WASM"
+    )]
+    debug_file: Option<PathBuf>,
+
+    #[arg(
+        short,
+        long,
+        help = "The path of the archive (default: <name>.bundle.tar)"
+    )]
+    output: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        help = "Compress the archive (i.e. <name>.bundle.tar.gz): gzip"
+    )]
+    compress: Option<Compression>,
+
+    #[arg(
+        long,
+        value_name = "REV",
+        help = "Archive the sourcefiles as they are in this commit of the git repository in the current directory"
+    )]
+    sources_from_git: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "POLICY",
+        help = "What to do with sourcefiles changed since the build: ignore, skip, warn (default) or fail"
+    )]
+    stale_sources: Option<StaleSources>,
+
+    #[command(flatten)]
+    options: MapArgs,
+}
+
+#[derive(Args)]
+struct ServeSymbolsArgs {
+    #[arg(
+        required = true,
+        value_name = "[ID=]PATH",
+        help = "The WASM files with debug info or the .map sourcemaps to symbolicate, by build id (default: the filename without .wasm or .map)"
+    )]
+    builds: Vec<String>,
+
+    #[arg(
+        short,
+        long,
+        value_name = "ADDRESS",
+        help = "The address to listen on (default: 127.0.0.1:8080)"
+    )]
+    listen: Option<String>,
 }
 
 fn main() -> Result<(), String> {
     // Parse the command parameters
-    let CargoCli::Wasm2map(mut args) = CargoCli::parse();
+    let CargoCli::Wasm2map(args) = CargoCli::parse();
+
+    match args.command {
+        Some(Command::Split(args)) => split(args),
+        Some(Command::Size(args)) => size(args),
+        Some(Command::Report(args)) => report(args),
+        Some(Command::Symbolicate(args)) => symbolicate(args),
+        Some(Command::Bundle(args)) => bundle(*args),
+        Some(Command::ServeSymbols(args)) => serve_symbols(args),
+        Some(Command::Info(args)) => info(args),
+        None => map(args.map),
+    }
+}
 
-    // Check if the WASM path points to a file
-    if !args.path.is_file() {
+// Check if the WASM path points to a file
+fn check_wasm_path(path: &Path) -> Result<(), String> {
+    if !path.is_file() {
         return Err(format!(
             "The WASM file path provided is not a file, {} was provided",
-            args.path.display()
+            path.display()
         ));
     }
 
+    Ok(())
+}
+
+// The default path of the separate debug info file, which is the WASM file
+// path with the .wasm extension replaced by .debug.wasm
+fn default_debug_path(path: &Path) -> PathBuf {
+    let mut debug = path.with_extension("");
+    let mut filename = debug.file_name().unwrap().to_owned();
+    filename.push(".debug.wasm");
+    debug.set_file_name(filename);
+
+    debug
+}
+
+// Move the DWARF info of the WASM to the debug file and link it from the
+// WASM with the --debug-url parameter or the filename of the debug file
+fn split_debug_info(
+    wasm: &mut WASM,
+    debug: &Path,
+    debug_url: Option<String>,
+) -> Result<(), String> {
+    let url = debug_url.unwrap_or_else(|| debug.file_name().unwrap().to_string_lossy().into());
+    wasm.split_debug_info(debug, &url)
+        .map_err(|err| err.to_string())
+}
+
+fn split(args: SplitArgs) -> Result<(), String> {
+    check_wasm_path(&args.path)?;
+
+    let debug = args
+        .debug_file
+        .unwrap_or_else(|| default_debug_path(&args.path));
+
+    let mut wasm = WASM::load(&args.path).map_err(|err| err.to_string())?;
+    split_debug_info(&mut wasm, &debug, args.debug_url)
+}
+
+// Load the WASM with the debug info from the separate file if provided
+fn load(path: &Path, debug_file: Option<&Path>) -> Result<WASM, String> {
+    let options = match debug_file {
+        Some(debug_file) => LoadOptions::new().debug_file(debug_file),
+        None => LoadOptions::new(),
+    };
+    let wasm = WASM::load_with(path, &options).map_err(|err| err.to_string())?;
+    if let Some(debug_file) = wasm.missing_debug_file() {
+        eprintln!(
+            "warning: the debug info file {} referenced by the WASM does not exist",
+            debug_file.display()
+        );
+    }
+
+    Ok(wasm)
+}
+
+// Parse the FROM=TO value of --remap-path-prefix, where FROM can contain
+// an = too, the same way as rustc does
+fn parse_remap(value: &str) -> Result<(PathBuf, PathBuf), String> {
+    value
+        .rsplit_once('=')
+        .map(|(from, to)| (PathBuf::from(from), PathBuf::from(to)))
+        .ok_or_else(|| format!("Expected FROM=TO, got '{}'", value))
+}
+
+// The sourcemap options set by the parameters
+fn map_options(args: &WasmFile) -> Result<MapOptions, String> {
+    let options = MapOptions::new()
+        .bundle(args.bundle_sources)
+        .stale_sources(args.stale_sources.unwrap_or_default())
+        .on_stale_source(warn_stale_source);
+
+    sources_from_git(
+        shared_map_options(options, &args.options),
+        args.sources_from_git.as_deref(),
+    )
+}
+
+// Adds the sourcemap options shared by the commands to 'options'
+fn shared_map_options(options: MapOptions, args: &MapArgs) -> MapOptions {
+    let options = options
+        .columns(args.columns.unwrap_or_default())
+        .canonical(args.canonical)
+        .pretty(args.pretty)
+        .names(args.names);
+    let options = match &args.source_root {
+        Some(source_root) => options.source_root(source_root),
+        None => options,
+    };
+
+    let options = args.include.iter().fold(options, MapOptions::include);
+    let options = args.exclude.iter().fold(options, MapOptions::exclude);
+    let options = args.crates.iter().fold(options, MapOptions::include_crate);
+    let options = args
+        .exclude_crates
+        .iter()
+        .fold(options, MapOptions::exclude_crate);
+    let options = args.kinds.iter().copied().fold(options, MapOptions::kind);
+    let options = args
+        .remap_path_prefix
+        .iter()
+        .fold(options, |options, (from, to)| {
+            options.remap_path_prefix(from, to)
+        });
+    let options = args.ignore.iter().fold(options, MapOptions::ignore);
+    args.ignore_kind
+        .iter()
+        .copied()
+        .fold(options, MapOptions::ignore_kind)
+}
+
+// Prints a warning for a stale source file bundled with --stale-sources warn
+fn warn_stale_source(path: &Path, reason: &str) {
+    eprintln!(
+        "warning: bundling stale source file {}, {}",
+        path.display(),
+        reason
+    );
+}
+
+// Reads the sources from the 'revision' of the git repository in the current
+// directory, if set
+fn sources_from_git(options: MapOptions, revision: Option<&str>) -> Result<MapOptions, String> {
+    Ok(match revision {
+        Some(revision) => {
+            let sources = GitRevision::new(".", revision).map_err(|err| err.to_string())?;
+            options.source_provider(sources)
+        }
+        None => options,
+    })
+}
+
+fn size(args: SizeArgs) -> Result<(), String> {
+    check_wasm_path(&args.path)?;
+
+    let wasm = load(&args.path, args.debug_file.as_deref())?;
+    let report = wasm.size_report().map_err(|err| err.to_string())?;
+
+    if args.json {
+        println!("{}", report.to_json());
+        return Ok(());
+    }
+
+    let (title, entries) = match args.by.as_str() {
+        "file" => ("File", &report.files),
+        "function" => ("Function", &report.functions),
+        _ => ("Crate", &report.crates),
+    };
+    let share = |size: u64| match report.total {
+        0 => 0.0,
+        total => size as f64 * 100.0 / total as f64,
+    };
+
+    println!("{:>10} {:>7}  {}", "Bytes", "Share", title);
+    for entry in entries.iter().take(args.top.unwrap_or(usize::MAX)) {
+        println!(
+            "{:>10} {:>6.2}%  {}",
+            entry.size,
+            share(entry.size),
+            entry.name
+        );
+    }
+    println!("{:>10} {:>6.2}%  Total", report.total, share(report.total));
+
+    Ok(())
+}
+
+fn report(args: ReportArgs) -> Result<(), String> {
+    check_wasm_path(&args.path)?;
+
+    let wasm = load(&args.path, args.debug_file.as_deref())?;
+    let options = sources_from_git(
+        MapOptions::new().canonical(args.canonical),
+        args.sources_from_git.as_deref(),
+    )?;
+    let report = wasm
+        .quality_report_with(&options)
+        .map_err(|err| err.to_string())?;
+
+    if args.json {
+        println!("{}", report.to_json());
+        return Ok(());
+    }
+
+    println!(
+        "{} of {} code bytes ({:.2}%) are covered by line info",
+        report.covered,
+        report.total,
+        report.percentage()
+    );
+    println!("{} line 0 rows", report.line_zero_rows);
+    println!("{} tombstoned sequences", report.tombstoned_sequences);
+
+    let table = |title: &str, entries: &[Coverage], top: usize| {
+        println!();
+        println!(
+            "{:>10} {:>10} {:>8} {:>7}  {}",
+            "Bytes", "Covered", "Share", "Line 0", title
+        );
+        for entry in entries.iter().take(top) {
+            println!(
+                "{:>10} {:>10} {:>7.2}% {:>7}  {}",
+                entry.size,
+                entry.covered,
+                entry.percentage(),
+                entry.line_zero_rows,
+                entry.name
+            );
+        }
+    };
+    table("Crate", &report.crates, usize::MAX);
+    table("Function", &report.functions, args.top.unwrap_or(20));
+
+    if !report.unresolved_sources.is_empty() {
+        println!();
+        println!("Source files not found:");
+        for path in &report.unresolved_sources {
+            println!("  {}", path.display());
+        }
+    }
+    if !report.unreadable_sources.is_empty() {
+        println!();
+        println!("Source files which can't be read:");
+        for (path, reason) in &report.unreadable_sources {
+            println!("  {} ({})", path.display(), reason);
+        }
+    }
+    if !report.stale_sources.is_empty() {
+        println!();
+        println!("Source files changed since the build:");
+        for (path, reason) in &report.stale_sources {
+            println!("  {} ({})", path.display(), reason);
+        }
+    }
+
+    Ok(())
+}
+
+fn info(args: InfoArgs) -> Result<(), String> {
+    check_wasm_path(&args.path)?;
+
+    // A missing or broken companion debug file referenced by the WASM
+    // doesn't stop inspecting the module itself
+    let (wasm, debug_error) = match &args.debug_file {
+        Some(debug_file) => (load(&args.path, Some(debug_file))?, None),
+        None => match WASM::load(&args.path) {
+            Ok(wasm) => (wasm, None),
+            Err(err) => {
+                let options = LoadOptions::new().external_debug_info(false);
+                let wasm = WASM::load_with(&args.path, &options).map_err(|err| err.to_string())?;
+                (wasm, Some(err.to_string()))
+            }
+        },
+    };
+    let sections = wasm.sections().map_err(|err| err.to_string())?;
+    let url = wasm.source_mapping_url().map_err(|err| err.to_string())?;
+    let producers = wasm.producers().map_err(|err| err.to_string())?;
+    let units = wasm.compilation_units();
+
+    println!("Module:           {}", args.path.display());
+    println!("Debug ID:         {}", wasm.debug_id());
+    println!(
+        "Debug info:       {}",
+        match (wasm.debug_file(), wasm.missing_debug_file(), &debug_error) {
+            (_, _, Some(err)) => format!("unreadable, {}", err),
+            (Some(debug_file), _, _) => debug_file.display().to_string(),
+            (_, Some(debug_file), _) => format!("{} (missing)", debug_file.display()),
+            _ if units.is_empty() => "none".to_owned(),
+            _ => "embedded".to_owned(),
+        }
+    );
+    println!(
+        "sourceMappingURL: {}",
+        match &url {
+            Some(url) if url.starts_with("data:") => "inline data URL",
+            Some(url) => url,
+            None => "none",
+        }
+    );
+
+    println!();
+    println!("{:>10} {:>10}  Section", "Offset", "Size");
+    for section in &sections {
+        println!(
+            "{:>#10x} {:>10}  {}{}",
+            section.offset,
+            section.size,
+            section.name,
+            if section.is_custom() { " (custom)" } else { "" }
+        );
+    }
+
+    if !producers.is_empty() {
+        println!();
+        println!("Producers:");
+        for producer in &producers {
+            match producer.version.as_str() {
+                "" => println!("  {}: {}", producer.field, producer.name),
+                version => println!("  {}: {} {}", producer.field, producer.name, version),
+            }
+        }
+    }
+
+    if !units.is_empty() {
+        let mut versions = units.iter().map(|unit| unit.version).collect::<Vec<_>>();
+        versions.sort_unstable();
+        versions.dedup();
+        let versions = versions
+            .iter()
+            .map(u16::to_string)
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        println!();
+        println!("{} DWARF units (version {}):", units.len(), versions);
+        for unit in units {
+            println!(
+                "  {} ({}, {})",
+                unit.name.as_deref().unwrap_or("<unnamed>"),
+                unit.language.as_deref().unwrap_or("unknown language"),
+                unit.producer.as_deref().unwrap_or("unknown producer")
+            );
+        }
+    }
+
+    Ok(())
+}
+
+fn symbolicate(args: SymbolicateArgs) -> Result<(), String> {
+    check_wasm_path(&args.path)?;
+
+    let trace = match &args.trace {
+        Some(path) if path.as_os_str() != "-" => fs::read_to_string(path),
+        _ => {
+            let mut trace = String::new();
+            io::stdin().read_to_string(&mut trace).map(|_| trace)
+        }
+    }
+    .map_err(|err| format!("Failed to read the stack trace, {}", err))?;
+
+    let wasm = load(&args.path, args.debug_file.as_deref())?;
+    if let Some(debug_id) = &args.debug_id {
+        wasm.verify_debug_id(debug_id)
+            .map_err(|err| err.to_string())?;
+    }
+    if let Some(map) = &args.map {
+        let sourcemap = fs::read_to_string(map)
+            .map_err(|err| format!("Failed to read the sourcemap {}, {}", map.display(), err))?;
+        wasm.verify_map(&sourcemap)
+            .map_err(|err| format!("{} does not belong to the WASM, {}", map.display(), err))?;
+    }
+    println!("{}", wasm.symbolicate(&trace));
+
+    Ok(())
+}
+
+fn bundle(args: BundleArgs) -> Result<(), String> {
+    check_wasm_path(&args.path)?;
+
+    let output = args.output.unwrap_or_else(|| {
+        let mut output = args.path.with_extension("");
+        let mut filename = output.file_name().unwrap().to_owned();
+        filename.push(".bundle.tar");
+        if let Some(compression) = args.compress {
+            filename.push(".");
+            filename.push(compression.extension());
+        }
+        output.set_file_name(filename);
+        output
+    });
+
+    let wasm = load(&args.path, args.debug_file.as_deref())?;
+    let options = MapOptions::new()
+        .stale_sources(args.stale_sources.unwrap_or_default())
+        .on_stale_source(warn_stale_source);
+    let options = shared_map_options(options, &args.options);
+    let options = sources_from_git(options, args.sources_from_git.as_deref())?;
+    let mut archive = Vec::new();
+    wasm.bundle(&mut archive, &options)
+        .map_err(|err| err.to_string())?;
+    if let Some(compression) = args.compress {
+        archive = compression
+            .compress(&archive)
+            .map_err(|err| err.to_string())?;
+    }
+
+    fs::write(&output, archive)
+        .map_err(|err| format!("Failed to write {}, {}", output.display(), err))
+}
+
+fn serve_symbols(args: ServeSymbolsArgs) -> Result<(), String> {
+    let mut server = SymbolServer::new();
+    for build in &args.builds {
+        let (id, path) = match build.split_once('=') {
+            Some((id, path)) => (id.to_owned(), PathBuf::from(path)),
+            None => {
+                let path = PathBuf::from(build);
+                let id = path
+                    .file_name()
+                    .map(|name| {
+                        let name = name.to_string_lossy();
+                        let name = name.strip_suffix(".map").unwrap_or(&name);
+                        name.strip_suffix(".wasm").unwrap_or(name).to_owned()
+                    })
+                    .unwrap_or_else(|| build.clone());
+                (id, path)
+            }
+        };
+        match path.extension() {
+            Some(extension) if extension == "map" => {
+                if !path.is_file() {
+                    return Err(format!(
+                        "The sourcemap path provided is not a file, {} was provided",
+                        path.display()
+                    ));
+                }
+                server.add_map(id, path);
+            }
+            _ => {
+                check_wasm_path(&path)?;
+                server.add(id, path);
+            }
+        }
+    }
+
+    let address = args.listen.as_deref().unwrap_or("127.0.0.1:8080");
+    let listener = TcpListener::bind(address)
+        .map_err(|err| format!("Failed to listen on {}, {}", address, err))?;
+    println!("Serving symbols on http://{}", address);
+    for id in server.builds() {
+        println!("  {}", id);
+    }
+
+    server.serve(&listener).map_err(|err| err.to_string())
+}
+
+fn map(mut args: WasmFile) -> Result<(), String> {
+    let path = args.path.take().expect("The WASM path is required");
+    check_wasm_path(&path)?;
+
     // Parse the --mapfile parameter or set a default
     // path based on the WASM file path and filename
     let map = if let Some(map) = args.map_path.take() {
@@ -90,8 +964,14 @@ fn main() -> Result<(), String> {
     } else {
         // No --mapfile parameter, so by default take the
         // WASM file path and append ".map" to the path
-        let mut map = args.path.clone();
-        let mut filename = args.path.file_name().unwrap().to_owned();
+        // (without the extension of a compressed WASM)
+        let mut map = path.clone();
+        let mut filename = match path.extension() {
+            Some(extension) if extension == "gz" || extension == "br" => {
+                path.file_stem().unwrap().to_owned()
+            }
+            _ => path.file_name().unwrap().to_owned(),
+        };
         filename.push(".map");
         map.set_file_name(filename);
 
@@ -101,14 +981,29 @@ fn main() -> Result<(), String> {
     // TODO(mtolamcs): Test the base url parameter to make sure its a valid
     // url and it also does not reference the map file
 
-    // Load the WASM file to memory and parse the DWARF code section
-    let mut wasm = WASM::load(&args.path).map_err(|err| err.to_string())?;
+    // Load the WASM file to memory and parse the DWARF code section, either
+    // from the WASM itself or the separate debug info file
+    let mut wasm = load(&path, args.debug_file.as_deref())?;
 
     // Generate the source map JSON for the loaded WASM
-    let sourcemap = wasm.map_v3(args.bundle_sources);
+    let options = map_options(&args)?;
+    let sourcemap = wasm.map_v3_with(&options).map_err(|err| err.to_string())?;
 
-    // Dump JSON to the map file
-    fs::write(&map, sourcemap).map_err(|err| err.to_string())?;
+    // Dump JSON to the map file and/or its compressed version, which is
+    // still referenced by the plain name, as servers pick the compressed
+    // file by the Accept-Encoding of the request
+    if !args.compressed_only && !args.inline {
+        fs::write(&map, &sourcemap).map_err(|err| err.to_string())?;
+    }
+    if let Some(compression) = args.compress {
+        let mut compressed = map.clone().into_os_string();
+        compressed.push(".");
+        compressed.push(compression.extension());
+        let data = compression
+            .compress(sourcemap.as_bytes())
+            .map_err(|err| err.to_string())?;
+        fs::write(&compressed, data).map_err(|err| err.to_string())?;
+    }
 
     // If patching is requested, then patch the WASM file at the parameter
     // with the provided source bap base url + the mapfile name
@@ -119,6 +1014,31 @@ fn main() -> Result<(), String> {
             map.file_name().unwrap().to_str().unwrap()
         );
         wasm.patch(&url).map_err(|err| err.to_string())?;
+
+        // Tie the WASM to the sourcemap even if the code changes later
+        if args.build_id {
+            wasm.add_build_id().map_err(|err| err.to_string())?;
+        }
+    }
+
+    // Embed the sourcemap itself in the WASM, so no separate file is needed
+    if args.inline {
+        wasm.patch_inline(&sourcemap)
+            .map_err(|err| err.to_string())?;
+    }
+    if args.embed {
+        wasm.embed_map(&sourcemap).map_err(|err| err.to_string())?;
+    }
+
+    // Move the DWARF info out of the WASM now that the sourcemap is generated
+    if args.split {
+        split_debug_info(&mut wasm, &default_debug_path(&path), args.debug_url)?;
+    }
+
+    // Remove the DWARF info from the WASM now that the sourcemap is generated
+    if args.strip {
+        wasm.strip_debug(args.strip_names)
+            .map_err(|err| err.to_string())?;
     }
 
     Ok(())
@@ -0,0 +1,52 @@
+// Simple implementation of the standard Base64 encoding (RFC 4648) for the
+// sourcemaps inlined as data URLs, without pulling in yet another dependency
+
+const BASE64_CHARS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+pub(crate) fn encode(data: &[u8]) -> String {
+    let mut result = String::with_capacity((data.len() + 2) / 3 * 4);
+
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let bits = u32::from(bytes[0]) << 16 | u32::from(bytes[1]) << 8 | u32::from(bytes[2]);
+
+        for index in 0..4 {
+            if index <= chunk.len() {
+                let digit = (bits >> (18 - index * 6)) & 0x3f;
+                result.push(BASE64_CHARS[digit as usize].into());
+            } else {
+                result.push('=');
+            }
+        }
+    }
+
+    result
+}
+
+// Decodes the Base64 'text', with or without the padding, or returns None if
+// it's not valid Base64
+pub(crate) fn decode(text: &str) -> Option<Vec<u8>> {
+    let text = text.trim_end_matches('=').as_bytes();
+    if text.len() % 4 == 1 {
+        return None;
+    }
+
+    let mut result = Vec::with_capacity(text.len() / 4 * 3 + 2);
+    for chunk in text.chunks(4) {
+        let mut bits: u32 = 0;
+        for &char in chunk {
+            let digit = BASE64_CHARS.iter().position(|&digit| digit == char)?;
+            bits = bits << 6 | digit as u32;
+        }
+        bits <<= 6 * (4 - chunk.len());
+
+        let bytes = bits.to_be_bytes();
+        result.extend_from_slice(&bytes[1..chunk.len()]);
+    }
+
+    Some(result)
+}
@@ -0,0 +1,102 @@
+use crate::error::Error;
+use std::{borrow::Cow, str::FromStr};
+
+/// How to translate the DWARF column numbers to sourcemap columns.
+///
+/// Sourcemap columns are counted in UTF-16 code units, while DWARF columns
+/// are byte (rustc, clang) or character offsets, so on lines with non-ASCII
+/// text before the position the columns point to the wrong character unless
+/// they're translated. Translating requires reading the source line, so when
+/// the source is not available the column is left as is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColumnMode {
+    /// Copy the DWARF columns to the sourcemap unchanged
+    #[default]
+    Raw,
+    /// The DWARF columns are UTF-8 byte offsets (as emitted by rustc and clang)
+    Bytes,
+    /// The DWARF columns are Unicode character offsets
+    Chars,
+}
+
+impl FromStr for ColumnMode {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "raw" => Ok(Self::Raw),
+            "bytes" => Ok(Self::Bytes),
+            "chars" => Ok(Self::Chars),
+            _ => Err(format!(
+                "Unknown column mode '{}', expected one of raw, bytes or chars",
+                value
+            )
+            .into()),
+        }
+    }
+}
+
+// The source code of a file split to lines, for translating columns
+pub(crate) struct SourceLines<'a> {
+    content: Cow<'a, str>,
+    starts: Vec<usize>,
+}
+
+impl<'a> SourceLines<'a> {
+    pub(crate) fn new(content: Cow<'a, str>) -> Self {
+        let starts = std::iter::once(0)
+            .chain(content.match_indices('\n').map(|(index, _)| index + 1))
+            .collect();
+
+        Self { content, starts }
+    }
+
+    // The 1-based 'line' of the source without the line ending
+    pub(crate) fn line(&self, line: u64) -> Option<&str> {
+        let index = usize::try_from(line).ok()?.checked_sub(1)?;
+        let start = *self.starts.get(index)?;
+        let end = self
+            .starts
+            .get(index + 1)
+            .map(|end| end - 1)
+            .unwrap_or(self.content.len());
+
+        Some(self.content[start..end].trim_end_matches('\r'))
+    }
+}
+
+// Translates the 1-based DWARF 'column' on the source 'line' to the 1-based
+// column in UTF-16 code units. Columns past the end of the line are moved by
+// the same amount as the end of the line.
+pub(crate) fn to_utf16(line: &str, column: u64, mode: ColumnMode) -> u64 {
+    // Column 0 means the position is not known, so there's nothing to move
+    if column == 0 || mode == ColumnMode::Raw {
+        return column;
+    }
+
+    let offset = column - 1;
+    let (prefix, rest) = match mode {
+        ColumnMode::Raw => unreachable!(),
+        ColumnMode::Bytes => {
+            let mut end = usize::try_from(offset)
+                .unwrap_or(usize::MAX)
+                .min(line.len());
+            // A column in the middle of a character points to that character
+            while !line.is_char_boundary(end) {
+                end -= 1;
+            }
+            (&line[..end], offset.saturating_sub(line.len() as u64))
+        }
+        ColumnMode::Chars => {
+            let end = line
+                .char_indices()
+                .nth(usize::try_from(offset).unwrap_or(usize::MAX))
+                .map(|(index, _)| index)
+                .unwrap_or(line.len());
+            let chars = line[..end].chars().count() as u64;
+            (&line[..end], offset - chars)
+        }
+    };
+
+    prefix.encode_utf16().count() as u64 + rest + 1
+}
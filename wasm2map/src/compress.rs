@@ -0,0 +1,79 @@
+use crate::error::Error;
+use flate2::{read::MultiGzDecoder, write::GzEncoder};
+use std::{
+    io::{Read, Write},
+    path::Path,
+    str::FromStr,
+};
+
+// The first bytes of a gzip stream
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+
+/// The compression of the sourcemap written next to (or instead of) the
+/// plain one, to be served pre-compressed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    /// gzip, written as `<name>.gz`
+    Gzip,
+}
+
+impl FromStr for Compression {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "gzip" | "gz" => Ok(Self::Gzip),
+            _ => Err(format!("Unknown compression '{}', expected gzip", value).into()),
+        }
+    }
+}
+
+impl Compression {
+    /// The extension of the compressed file, appended to the name of the
+    /// plain file (i.e. `myproject.wasm.map.gz`)
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Gzip => "gz",
+        }
+    }
+
+    /// Compresses 'data' with the best compression, as the sourcemaps are
+    /// compressed once and served many times
+    pub fn compress(self, data: &[u8]) -> Result<Vec<u8>, Error> {
+        match self {
+            Self::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::best());
+                encoder.write_all(data)?;
+                Ok(encoder.finish()?)
+            }
+        }
+    }
+}
+
+// Decompresses the WASM file under 'path' if it's compressed, or returns
+// None if 'raw' is already a plain WASM binary
+pub(crate) fn decompress(path: &Path, raw: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+    if raw.starts_with(GZIP_MAGIC) {
+        let mut wasm = Vec::new();
+        MultiGzDecoder::new(raw)
+            .read_to_end(&mut wasm)
+            .map_err(|err| format!("Failed to decompress {}: {}", path.display(), err))?;
+        return Ok(Some(wasm));
+    }
+
+    // Brotli streams have no magic number to recognize them by
+    if path
+        .extension()
+        .map(|extension| extension == "br")
+        .unwrap_or(false)
+        && !raw.starts_with(b"\0asm")
+    {
+        return Err(format!(
+            "Brotli compressed WASM files are not supported, decompress {} first",
+            path.display()
+        )
+        .into());
+    }
+
+    Ok(None)
+}
@@ -0,0 +1,62 @@
+use crate::{error::Error, md5, vlq};
+
+// The name of the custom section with the build id of the module, as
+// specified by the WebAssembly tool conventions
+pub(crate) const BUILD_ID_SECTION: &str = "build_id";
+
+// Reads the build id from the payload of the build_id custom section, which
+// is a single length prefixed byte vector
+pub(crate) fn read_build_id(data: &[u8]) -> Result<&[u8], Error> {
+    vlq::decode_uint_var(data)
+        .and_then(|(length, start)| {
+            let end = start.checked_add(usize::try_from(length).ok()?)?;
+            data.get(start..end)
+        })
+        .filter(|build_id| !build_id.is_empty())
+        .ok_or_else(|| "Malformed build_id section in WASM".into())
+}
+
+// The 16 bytes of the debug ID of the module: the build id if it's 16 bytes
+// long (as written by WASM::add_build_id), or the MD5 digest of the build id
+// or the code section otherwise, marked as a name based (version 3) UUID
+pub(crate) fn compute(build_id: Option<&[u8]>, code: &[u8]) -> [u8; 16] {
+    if let Some(build_id) = build_id {
+        if let Ok(bytes) = <[u8; 16]>::try_from(build_id) {
+            return bytes;
+        }
+    }
+
+    let mut bytes = md5::digest(build_id.unwrap_or(code));
+    bytes[6] = (bytes[6] & 0x0f) | 0x30;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    bytes
+}
+
+// Formats the debug ID as a UUID, i.e. `85314830-023f-3cf1-a267-535f4e37bb17`
+pub(crate) fn format(bytes: &[u8; 16]) -> String {
+    let hex = bytes
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<String>();
+
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+// Checks if two debug IDs are the same, regardless of the case and dashes
+pub(crate) fn matches(a: &str, b: &str) -> bool {
+    let digits = |id: &str| {
+        id.bytes()
+            .filter(|&byte| byte != b'-')
+            .map(|byte| byte.to_ascii_lowercase())
+            .collect::<Vec<_>>()
+    };
+
+    digits(a) == digits(b)
+}
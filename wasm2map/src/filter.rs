@@ -0,0 +1,68 @@
+use crate::{MapOptions, Source};
+
+// Decides whether the 'source' file is covered by the sourcemap according to
+// the include/exclude globs and crate and kind filters of 'options'
+pub(crate) fn is_included(source: &Source, options: &MapOptions) -> bool {
+    let text = source.path.to_string_lossy().replace('\\', "/");
+    let glob = |pattern: &String| glob_match(pattern, &text);
+    let is_crate = |name: &String| source.is_crate(name);
+
+    (options.include.is_empty() || options.include.iter().any(glob))
+        && !options.exclude.iter().any(glob)
+        && (options.crates.is_empty() || options.crates.iter().any(is_crate))
+        && !options.exclude_crates.iter().any(is_crate)
+        && (options.kinds.is_empty() || options.kinds.contains(&source.kind))
+}
+
+// Decides whether the 'source' file goes on the ignoreList of the sourcemap
+// according to the ignore globs and kinds of 'options'
+pub(crate) fn is_ignored(source: &Source, options: &MapOptions) -> bool {
+    let text = source.path.to_string_lossy().replace('\\', "/");
+
+    options
+        .ignore
+        .iter()
+        .any(|pattern| glob_match(pattern, &text))
+        || options.ignore_kinds.contains(&source.kind)
+}
+
+// Matches 'text' against the glob 'pattern', where `?` matches any single
+// character and `*` any number of them except `/`, while `**` matches across
+// directories (and `**/` also matches no directory at all).
+//
+// Backtracking over the stars would take exponential time on patterns like
+// `*a*a*a*b`, so it's decided for every suffix of the pattern, from the last
+// one, which suffixes of the text it matches, in O(pattern * text) time.
+pub(crate) fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let text = text.chars().collect::<Vec<_>>();
+    let width = text.len() + 1;
+
+    // matches[i * width + j] tells if pattern[i..] matches text[j..]
+    let mut matches = vec![false; (pattern.len() + 1) * width];
+    matches[pattern.len() * width + text.len()] = true;
+
+    for i in (0..pattern.len()).rev() {
+        // Whether the pattern after a `**` matches any suffix from j on
+        let mut rest_matches = false;
+
+        for j in (0..=text.len()).rev() {
+            let at = |i: usize, j: usize| matches[i * width + j];
+            // Whether there's a next character which is not a separator
+            let in_segment = text.get(j).filter(|&&char| char != '/').is_some();
+
+            let matched = match pattern[i] {
+                '*' if pattern.get(i + 1) == Some(&'*') => {
+                    rest_matches |= at(i + 2, j);
+                    rest_matches || (pattern.get(i + 2) == Some(&'/') && at(i + 3, j))
+                }
+                '*' => at(i + 1, j) || (in_segment && at(i, j + 1)),
+                '?' => in_segment && at(i + 1, j + 1),
+                char => text.get(j) == Some(&char) && at(i + 1, j + 1),
+            };
+            matches[i * width + j] = matched;
+        }
+    }
+
+    matches[0]
+}
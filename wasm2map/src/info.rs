@@ -0,0 +1,109 @@
+use crate::{error::Error, section, vlq};
+use std::{path::PathBuf, str};
+
+/// A section of the WASM module (see [`crate::WASM::sections`])
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SectionInfo {
+    /// The section id, 0 for custom sections
+    pub id: u8,
+    /// The name of a custom section, or the name of the section id for the
+    /// rest, i.e. `code`
+    pub name: String,
+    /// The offset of the section header in the (decompressed) WASM file
+    pub offset: u64,
+    /// The size of the whole section, including the header
+    pub size: u64,
+}
+
+impl SectionInfo {
+    /// Whether this is a custom section
+    pub fn is_custom(&self) -> bool {
+        self.id == section::CUSTOM_SECTION_ID
+    }
+}
+
+/// A tool or language the WASM was produced with, as recorded in the
+/// `producers` custom section (see [`crate::WASM::producers`])
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Producer {
+    /// The kind of the producer: `language`, `processed-by` or `sdk`
+    pub field: String,
+    /// The name of the producer, i.e. `rustc` or `wasm-bindgen`
+    pub name: String,
+    /// The version of the producer (can be empty)
+    pub version: String,
+}
+
+/// A compilation unit of the DWARF info (see
+/// [`crate::WASM::compilation_units`])
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompilationUnit {
+    /// The DWARF version of the unit
+    pub version: u16,
+    /// The name of the unit, usually the path of the main source file
+    pub name: Option<String>,
+    /// The directory the unit was compiled in
+    pub comp_dir: Option<PathBuf>,
+    /// The compiler the unit was compiled with, i.e. `clang LLVM (rustc
+    /// version 1.75.0)`
+    pub producer: Option<String>,
+    /// The source language of the unit, i.e. `DW_LANG_Rust`
+    pub language: Option<String>,
+}
+
+// The name of a (non-custom) section by its id
+pub(crate) fn section_name(id: u8) -> &'static str {
+    match id {
+        section::CUSTOM_SECTION_ID => "custom",
+        1 => "type",
+        section::IMPORT_SECTION_ID => "import",
+        3 => "function",
+        4 => "table",
+        5 => "memory",
+        6 => "global",
+        7 => "export",
+        8 => "start",
+        9 => "element",
+        section::CODE_SECTION_ID => "code",
+        11 => "data",
+        12 => "datacount",
+        13 => "tag",
+        _ => "unknown",
+    }
+}
+
+// Reads the payload of the producers section, which is a vector of fields,
+// each with a name and a vector of name and version pairs
+pub(crate) fn read_producers(data: &[u8]) -> Result<Vec<Producer>, Error> {
+    let string = |pos: usize| -> Result<(String, usize), Error> {
+        let (bytes, end) = section::read_name(data, pos)?;
+        let string = str::from_utf8(bytes)
+            .map_err(|_| Error::from("Malformed producers section in WASM"))?;
+        Ok((string.to_owned(), end))
+    };
+    let count = |pos: usize| -> Result<(u64, usize), Error> {
+        vlq::decode_uint_var(&data[pos.min(data.len())..])
+            .map(|(count, length)| (count, pos + length))
+            .ok_or_else(|| "Malformed producers section in WASM".into())
+    };
+
+    let mut producers = Vec::new();
+    let (fields, mut pos) = count(0)?;
+    for _ in 0..fields {
+        let (field, end) = string(pos)?;
+        let (values, end) = count(end)?;
+        pos = end;
+        for _ in 0..values {
+            let (name, end) = string(pos)?;
+            let (version, end) = string(end)?;
+            pos = end;
+            producers.push(Producer {
+                field: field.clone(),
+                name,
+                version,
+            });
+        }
+    }
+
+    Ok(producers)
+}
@@ -1,3 +1,4 @@
+use crate::error::Error;
 use std::borrow::Cow;
 use std::str;
 
@@ -77,8 +78,296 @@ pub(crate) fn encode(string: &str) -> Cow<'_, str> {
     }
 
     if new_string.is_empty() {
-        string.into()
-    } else {
-        new_string.into()
+        return string.into();
+    }
+
+    // The rest of the string after the last escaped byte
+    new_string += &string[start..];
+    new_string.into()
+}
+
+// The value of a member of a JSON object written by object(), already
+// serialized, or the serialized items of an array
+pub(crate) enum Member {
+    Value(String),
+    Array(Vec<String>),
+}
+
+// Writes a JSON object with the 'members' in order, compact or indented with
+// two spaces and the array items on their own lines
+pub(crate) fn object(members: &[(&str, Member)], pretty: bool) -> String {
+    let (newline, indent, separator) = match pretty {
+        true => ("\n", "  ", ": "),
+        false => ("", "", ":"),
+    };
+
+    let members = members
+        .iter()
+        .map(|(name, value)| {
+            let value = match value {
+                Member::Value(value) => Cow::Borrowed(value.as_str()),
+                Member::Array(items) if items.is_empty() || !pretty => {
+                    Cow::Owned(format!("[{}]", items.join(",")))
+                }
+                Member::Array(items) => Cow::Owned(format!(
+                    "[\n{0}{0}{1}\n{0}]",
+                    indent,
+                    items.join(&format!(",\n{0}{0}", indent))
+                )),
+            };
+            format!(r#"{}"{}"{}{}"#, indent, encode(name), separator, value)
+        })
+        .collect::<Vec<_>>();
+
+    format!(
+        "{{{}{}{}}}",
+        newline,
+        members.join(&format!(",{}", newline)),
+        newline
+    )
+}
+
+// The parsed JSON document, with the numbers kept as they were written so
+// the large integers (i.e. offsets) don't lose precision
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Value {
+    Null,
+    Bool(bool),
+    Number(String),
+    String(String),
+    Array(Vec<Self>),
+    Object(Vec<(String, Self)>),
+}
+
+impl Value {
+    // Looks up the value of the 'key' member of an object
+    pub(crate) fn get(&self, key: &str) -> Option<&Self> {
+        match self {
+            Self::Object(members) => members
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+}
+
+// How deep arrays and objects can be nested, so a malicious document can't
+// overflow the stack
+const MAX_DEPTH: usize = 64;
+
+// Simple recursive descent JSON parser for the small documents the crate
+// reads, to avoid depending on serde_json for this
+pub(crate) fn decode(text: &str) -> Result<Value, Error> {
+    let mut parser = Parser {
+        text: text.as_bytes(),
+        pos: 0,
+    };
+    let value = parser.value(0)?;
+    parser.whitespace();
+    if parser.pos != parser.text.len() {
+        return Err(parser.error("Unexpected trailing characters"));
+    }
+
+    Ok(value)
+}
+
+struct Parser<'a> {
+    text: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, msg: &str) -> Error {
+        format!("Invalid JSON: {} at position {}", msg, self.pos).into()
+    }
+
+    fn whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.text.get(self.pos) {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, literal: &str) -> Result<(), Error> {
+        if self.text[self.pos..].starts_with(literal.as_bytes()) {
+            self.pos += literal.len();
+            Ok(())
+        } else {
+            Err(self.error(&format!("Expected '{}'", literal)))
+        }
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Value, Error> {
+        if depth > MAX_DEPTH {
+            return Err(self.error("Too deeply nested"));
+        }
+
+        self.whitespace();
+        match self.text.get(self.pos) {
+            Some(b'n') => self.expect("null").map(|_| Value::Null),
+            Some(b't') => self.expect("true").map(|_| Value::Bool(true)),
+            Some(b'f') => self.expect("false").map(|_| Value::Bool(false)),
+            Some(b'"') => self.string().map(Value::String),
+            Some(b'[') => {
+                self.pos += 1;
+                let mut items = Vec::new();
+                self.whitespace();
+                if self.text.get(self.pos) == Some(&b']') {
+                    self.pos += 1;
+                    return Ok(Value::Array(items));
+                }
+                loop {
+                    items.push(self.value(depth + 1)?);
+                    self.whitespace();
+                    match self.text.get(self.pos) {
+                        Some(b',') => self.pos += 1,
+                        Some(b']') => {
+                            self.pos += 1;
+                            return Ok(Value::Array(items));
+                        }
+                        _ => return Err(self.error("Expected ',' or ']'")),
+                    }
+                }
+            }
+            Some(b'{') => {
+                self.pos += 1;
+                let mut members = Vec::new();
+                self.whitespace();
+                if self.text.get(self.pos) == Some(&b'}') {
+                    self.pos += 1;
+                    return Ok(Value::Object(members));
+                }
+                loop {
+                    self.whitespace();
+                    if self.text.get(self.pos) != Some(&b'"') {
+                        return Err(self.error("Expected a member name"));
+                    }
+                    let name = self.string()?;
+                    self.whitespace();
+                    self.expect(":")?;
+                    members.push((name, self.value(depth + 1)?));
+                    self.whitespace();
+                    match self.text.get(self.pos) {
+                        Some(b',') => self.pos += 1,
+                        Some(b'}') => {
+                            self.pos += 1;
+                            return Ok(Value::Object(members));
+                        }
+                        _ => return Err(self.error("Expected ',' or '}'")),
+                    }
+                }
+            }
+            Some(b'-' | b'0'..=b'9') => self.number().map(Value::Number),
+            Some(_) => Err(self.error("Unexpected character")),
+            None => Err(self.error("Unexpected end")),
+        }
+    }
+
+    fn number(&mut self) -> Result<String, Error> {
+        let start = self.pos;
+        let digits = |parser: &mut Self| {
+            let start = parser.pos;
+            while let Some(b'0'..=b'9') = parser.text.get(parser.pos) {
+                parser.pos += 1;
+            }
+            parser.pos > start
+        };
+
+        if self.text.get(self.pos) == Some(&b'-') {
+            self.pos += 1;
+        }
+        if !digits(self) {
+            return Err(self.error("Expected a digit"));
+        }
+        if self.text.get(self.pos) == Some(&b'.') {
+            self.pos += 1;
+            if !digits(self) {
+                return Err(self.error("Expected a digit"));
+            }
+        }
+        if let Some(b'e' | b'E') = self.text.get(self.pos) {
+            self.pos += 1;
+            if let Some(b'+' | b'-') = self.text.get(self.pos) {
+                self.pos += 1;
+            }
+            if !digits(self) {
+                return Err(self.error("Expected a digit"));
+            }
+        }
+
+        // The number only consists of ASCII characters
+        Ok(str::from_utf8(&self.text[start..self.pos])
+            .unwrap()
+            .to_owned())
+    }
+
+    fn string(&mut self) -> Result<String, Error> {
+        // Skip the opening quote
+        self.pos += 1;
+        let mut bytes = Vec::new();
+
+        loop {
+            match self.text.get(self.pos) {
+                Some(b'"') => {
+                    self.pos += 1;
+                    // The input is a &str and the escapes are pushed as UTF-8
+                    return Ok(String::from_utf8(bytes).unwrap());
+                }
+                Some(b'\\') => {
+                    self.pos += 1;
+                    let escaped = match self.text.get(self.pos) {
+                        Some(b'"') => '"',
+                        Some(b'\\') => '\\',
+                        Some(b'/') => '/',
+                        Some(b'b') => '\u{8}',
+                        Some(b'f') => '\u{c}',
+                        Some(b'n') => '\n',
+                        Some(b'r') => '\r',
+                        Some(b't') => '\t',
+                        Some(b'u') => {
+                            let high = self.hex4()?;
+                            let code = if (0xD800..0xDC00).contains(&high) {
+                                self.pos += 1;
+                                self.expect("\\u")
+                                    .map_err(|_| self.error("Expected a low surrogate"))?;
+                                self.pos -= 1;
+                                let low = self.hex4()?;
+                                if !(0xDC00..0xE000).contains(&low) {
+                                    return Err(self.error("Expected a low surrogate"));
+                                }
+                                0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)
+                            } else {
+                                high
+                            };
+                            char::from_u32(code).ok_or_else(|| self.error("Invalid escape"))?
+                        }
+                        _ => return Err(self.error("Invalid escape")),
+                    };
+                    self.pos += 1;
+                    bytes.extend_from_slice(escaped.encode_utf8(&mut [0; 4]).as_bytes());
+                }
+                Some(0..=0x1F) => return Err(self.error("Unescaped control character")),
+                Some(&byte) => {
+                    bytes.push(byte);
+                    self.pos += 1;
+                }
+                None => return Err(self.error("Unterminated string")),
+            }
+        }
+    }
+
+    // Reads the 4 hex digits of a \u escape, leaving the position on the
+    // last digit
+    fn hex4(&mut self) -> Result<u32, Error> {
+        let digits = self
+            .text
+            .get(self.pos + 1..self.pos + 5)
+            .and_then(|digits| str::from_utf8(digits).ok())
+            .filter(|digits| digits.bytes().all(|byte| byte.is_ascii_hexdigit()))
+            .ok_or_else(|| self.error("Expected 4 hex digits"))?;
+        let code = u32::from_str_radix(digits, 16).unwrap();
+        self.pos += 4;
+
+        Ok(code)
     }
 }
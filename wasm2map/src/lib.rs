@@ -14,33 +14,108 @@
 //! * [wasm_sourcemap.py](https://github.com/emscripten-core/emscripten/blob/main/tools/wasm-sourcemap.py) by the Emscripten Team
 //! * [WebAssembly Debugging](https://medium.com/oasislabs/webassembly-debugging-bec0aa93f8c6) by Will Scott and Oasis Labs
 
+mod base64;
+mod column;
+mod compress;
+mod debug_id;
 mod error;
+mod filter;
+mod info;
 mod json;
+mod md5;
+mod options;
+mod provider;
+mod report;
+mod section;
+mod server;
+mod size;
+mod source;
+mod sourcemap;
+mod symbolicate;
+mod tar;
 #[cfg(test)]
 mod test;
+mod transform;
+mod url;
 mod vlq;
 
+pub use column::ColumnMode;
+use column::SourceLines;
+pub use compress::Compression;
 use error::Error;
+pub use info::{CompilationUnit, Producer, SectionInfo};
 use object::{Object, ObjectSection};
+pub use options::{LoadOptions, MapOptions, StaleSourceHandler, StaleSources};
+pub use provider::{FileSystem, GitRevision, SourceProvider};
+use report::CoverageTable;
+pub use report::{Coverage, QualityReport};
+use section::Section;
+pub use server::SymbolServer;
+use size::SizeTable;
+pub use size::{SizeEntry, SizeReport};
+pub use source::{Source, SourceKind};
 use std::{
     borrow::Cow,
-    collections::BTreeMap,
+    collections::{BTreeMap, HashMap},
     fs,
     io::{self, Seek, Write},
     ops::Deref,
     path::{Path, PathBuf},
     str,
+    time::SystemTime,
 };
+pub use symbolicate::Location;
+pub use transform::{Row, SourceEntry, Transform};
 
 const DWARF_CODE_SECTION_ID: usize = 10;
 
+// Source maps encode every position as a signed VLQ delta, which the source
+// map consumers (i.e. the browsers) decode to 32 bit integers, so addresses,
+// lines and columns past this value cannot be represented in the mappings
+const MAX_SOURCEMAP_POSITION: u64 = u32::MAX as u64;
+
+// The custom section the whole sourcemap JSON is embedded in by
+// WASM::embed_map
+const EMBEDDED_MAP_SECTION: &str = "sourceMap";
+
 /// Represents a code unit which can be translated to a sourcemap code point
 #[derive(Debug)]
 pub struct CodePoint {
     path: PathBuf,
-    address: i64,
-    line: i64,
-    column: i64,
+    address: u64,
+    line: u64,
+    column: u64,
+}
+
+// What the DWARF info tells about a source file besides its path
+#[derive(Debug, Default)]
+struct SourceFile {
+    // The source code embedded in the line program (DW_LNCT_LLVM_source),
+    // which clang emits with -gembed-source and rustc with -Zembed-source
+    content: Option<String>,
+    // The MD5 checksum of the source file (DWARF 5 only)
+    md5: Option<[u8; 16]>,
+    // The compilation directory of the unit first referencing the file, which
+    // is the workspace root for the crates built by cargo from the workspace
+    comp_dir: Option<PathBuf>,
+}
+
+// A function defined in the DWARF info, keyed by its start address
+#[derive(Debug)]
+struct Function {
+    // The address right after the end of the function
+    end: u64,
+    // The name of the function qualified with the namespaces and types
+    name: String,
+}
+
+// The code points and source files read from the DWARF info
+struct DebugInfo {
+    points: BTreeMap<u64, CodePoint>,
+    files: BTreeMap<PathBuf, SourceFile>,
+    functions: BTreeMap<u64, Function>,
+    tombstoned_sequences: u64,
+    units: Vec<CompilationUnit>,
 }
 
 /// The actual DWARF to Sourcemap mapper
@@ -59,185 +134,238 @@ pub struct CodePoint {
 #[derive(Debug)]
 pub struct WASM {
     path: PathBuf,
-    points: BTreeMap<i64, CodePoint>,
+    points: BTreeMap<u64, CodePoint>,
+    files: BTreeMap<PathBuf, SourceFile>,
+    // The functions keyed by their start address
+    functions: BTreeMap<u64, Function>,
+    // The number of line program sequences of dead code left in the DWARF
+    tombstoned_sequences: u64,
+    // The compilation units of the DWARF info
+    units: Vec<CompilationUnit>,
+    // The modification time of the file the DWARF info was read from
+    modified: Option<SystemTime>,
     sourcemap_size: Option<u64>,
+    // The debug ID tying the module to its sourcemap (see WASM::debug_id)
+    debug_id: [u8; 16],
+    // Whether the WASM file is compressed, so it can't be modified in place
+    compressed: bool,
+    // The separate file the DWARF info was read from, if any
+    debug_path: Option<PathBuf>,
+    // The companion file referenced by the external_debug_info section,
+    // if it doesn't exist
+    missing_debug_path: Option<PathBuf>,
 }
 
 struct Generated {
     mappings: Vec<String>,
     sources: Vec<String>,
-    contents: Option<Vec<Cow<'static, str>>>,
+    contents: Option<Vec<String>>,
+    names: Vec<String>,
+    // The indices of the sources to put on the ignoreList
+    ignore_list: Vec<usize>,
 }
 
 impl WASM {
     /// Loads the WASM file under 'path' into memory and parses the DWARF info
     /// If the WASM or the DWARF info in it is malformed (or non-existent)
     /// it returns with the appropriate error result.
+    ///
+    /// If the WASM has no DWARF info embedded, but references a companion
+    /// debug file in an `external_debug_info` custom section, the DWARF info is
+    /// read from that file instead (see [`WASM::load_with_debug_info`]). If
+    /// that file doesn't exist (i.e. a deployed module), the WASM is loaded
+    /// without DWARF info and the file is reported by
+    /// [`WASM::missing_debug_file`].
+    ///
+    /// Gzip compressed WASM files (i.e. `myproject.wasm.gz`) are decompressed
+    /// in memory, but they can't be patched or have their debug info split
+    /// or stripped. Brotli compressed files are not supported.
     pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
-        let path = path.as_ref().to_owned();
+        Self::load_with(path, &LoadOptions::default())
+    }
 
-        #[cfg(feature = "memmap2")]
-        let raw = {
-            // Load the WASM file into memory via mmap to speed things up
-            // with large WASM files
-            let file = fs::File::open(&path)?;
-            unsafe { memmap2::Mmap::map(&file) }?
-        };
-        #[cfg(not(feature = "memmap2"))]
-        let raw = {
-            // Load the WASM file via the standard library, which can be slower
-            // for larger WASM files, but some platforms might not be supported
-            // by memmap2
-            fs::read(&path)?
-        };
+    /// Loads the (stripped) WASM file under 'path' and reads the DWARF info
+    /// from the separate companion file under 'debug_path' (usually named
+    /// `<name>.debug.wasm`).
+    ///
+    /// The code offsets are always taken from the WASM under 'path', as that
+    /// is the module served to the browser. The companion file has to contain
+    /// the same code section as the WASM, otherwise the DWARF info would
+    /// point to the wrong instructions and an error is returned.
+    pub fn load_with_debug_info(
+        path: impl AsRef<Path>,
+        debug_path: impl AsRef<Path>,
+    ) -> Result<Self, Error> {
+        Self::load_with(
+            path,
+            &LoadOptions::default().debug_file(debug_path.as_ref()),
+        )
+    }
+
+    /// Loads the WASM file under 'path' with the given [`LoadOptions`] (see
+    /// [`WASM::load`] for details).
+    pub fn load_with(path: impl AsRef<Path>, options: &LoadOptions) -> Result<Self, Error> {
+        let path = path.as_ref().to_owned();
+        let raw = read_module(&path)?;
+        let compressed = matches!(raw, RawModule::Decompressed(_));
 
         // Parse the modules and sections from the WASM
         let object = object::File::parse(raw.deref())?;
 
         // Load the sourcemap custom section (if any) and calculate the total
         // size of the whole custom module (that is, the sourceMappingURL module)
-        let sourcemap_size = match object.section_by_name("sourceMappingURL") {
-            Some(section) => {
-                // This is the '0' section type
-                const CUSTOM_SEGMENT_ID_SIZE: u64 = 1;
-                // The size of the length b"sourceMappingURL" (which is always
-                // 1 byte, so the size of u8) + the length of the
-                // b"sourceMappingURL" byte array
-                const SEGMENT_NAME_SIZE: u64 =
-                    std::mem::size_of::<u8>() as u64 + b"sourceMappingURL".len() as u64;
-                let section_size_length = vlq::encode_uint_var(section.size() as u32).len() as u64;
-                let section_size = CUSTOM_SEGMENT_ID_SIZE
-                    + SEGMENT_NAME_SIZE
-                    + section_size_length
-                    + section.size();
-                Some(section_size)
-            }
-            None => None,
-        };
+        // from the actual section header, as the size fields might be padded.
+        // We can only replace the section later if it is the last one.
+        let sourcemap_size = section::read(raw.deref())?
+            .last()
+            .filter(|section| section.name == Some("sourceMappingURL"))
+            .map(|section| section.range.len() as u64);
 
         // Load the code section to get its offset
-        let offset: i64 = {
+        let offset: u64 = {
             let (code_section_offset, _) = object
                 .section_by_index(object::SectionIndex(DWARF_CODE_SECTION_ID))?
                 .file_range()
                 .ok_or("Missing code section in WASM")?;
-            code_section_offset.try_into()?
+            code_section_offset
         };
 
-        // Load all of the DWARF sections
-        let section =
-            gimli::Dwarf::load(|id: gimli::SectionId| -> Result<Cow<[u8]>, gimli::Error> {
-                match object.section_by_name(id.name()) {
-                    Some(ref section) => Ok(section
-                        .uncompressed_data()
-                        .unwrap_or(Cow::Borrowed(&[][..]))),
-                    None => Ok(Cow::Borrowed(&[][..])),
+        // The debug ID comes from the build_id section if the module has one,
+        // otherwise from the code, which is what the DWARF info describes
+        let build_id = object
+            .section_by_name(debug_id::BUILD_ID_SECTION)
+            .map(|section| section.data())
+            .transpose()?
+            .map(debug_id::read_build_id)
+            .transpose()?;
+        let debug_id = debug_id::compute(build_id, code_section(&object)?);
+
+        // Figure out where the DWARF info lives: an explicitly provided
+        // companion file, the module itself or the companion file referenced
+        // by the external_debug_info section (if it exists)
+        let mut missing_debug_path = None;
+        let debug_path = match &options.debug_file {
+            Some(debug_path) => Some(debug_path.clone()),
+            None if object.section_by_name(".debug_info").is_some() => None,
+            None if options.external_debug_info => match external_debug_info(&object, &path)? {
+                Some(debug_path) if !debug_path.is_file() => {
+                    missing_debug_path = Some(debug_path);
+                    None
                 }
-            })?;
+                debug_path => debug_path,
+            },
+            None => None,
+        };
 
-        // Borrow a `Cow<[u8]>` to create an `EndianSlice`.
-        let borrow_section: &dyn for<'a> Fn(
-            &'a Cow<[u8]>,
-        )
-            -> gimli::EndianSlice<'a, gimli::RunTimeEndian> =
-            &|section| gimli::EndianSlice::new(section, gimli::RunTimeEndian::Little);
-
-        // Create `EndianSlice`s for all of the sections.
-        let dwarf = section.borrow(&borrow_section);
-
-        // Collect the debug data and enforce that they are sorted by address
-        // which BTreeMap guarantees
-        let mut points: BTreeMap<i64, CodePoint> = BTreeMap::new();
-
-        let mut iter = dwarf.units();
-        while let Some(header) = iter.next()? {
-            let unit = dwarf.unit(header)?;
-
-            // Get the line program for the compilation unit.
-            if let Some(program) = unit.line_program.clone() {
-                // Iterate over the line program rows for the unit.
-                let mut rows = program.rows();
-                while let Some((header, row)) = rows.next_row()? {
-                    // We will collect the embdedded path from the DWARF loc metadata
-                    let mut path = PathBuf::new();
-
-                    if let Some(file) = row.file(header) {
-                        if let Some(dir) = file.directory(header) {
-                            let dir = &dwarf.attr_string(&unit, dir)?.to_string_lossy();
-                            let dir = Path::new(dir.as_ref());
-
-                            // Relative directories are relative to the compilation unit directory.
-                            if dir.is_relative() {
-                                if let Some(dir) = unit.comp_dir {
-                                    path.push(dir.to_string_lossy().as_ref())
-                                }
-                            }
+        let modified = fs::metadata(debug_path.as_ref().unwrap_or(&path))
+            .and_then(|metadata| metadata.modified())
+            .ok();
 
-                            path.push(dir);
-                        }
+        let DebugInfo {
+            points,
+            files,
+            functions,
+            tombstoned_sequences,
+            units,
+        } = match &debug_path {
+            Some(debug_path) => {
+                let debug_raw = read_module(debug_path)?;
+                let debug_object = object::File::parse(debug_raw.deref())?;
 
-                        path.push(
-                            dwarf
-                                .attr_string(&unit, file.path_name())?
-                                .to_string_lossy()
-                                .as_ref(),
-                        );
+                // The companion file must be built from the exact same code,
+                // otherwise the DWARF addresses are meaningless for this WASM
+                if code_section(&debug_object)? != code_section(&object)? {
+                    return Err(format!(
+                        "The debug info file {} does not match the WASM file {} (code sections differ)",
+                        debug_path.display(),
+                        path.display()
+                    )
+                    .into());
+                }
+                let debug_build_id = debug_object
+                    .section_by_name(debug_id::BUILD_ID_SECTION)
+                    .map(|section| section.data())
+                    .transpose()?
+                    .map(debug_id::read_build_id)
+                    .transpose()?;
+                if let (Some(build_id), Some(debug_build_id)) = (build_id, debug_build_id) {
+                    if build_id != debug_build_id {
+                        return Err(format!(
+                            "The debug info file {} does not match the WASM file {} (build ids differ)",
+                            debug_path.display(),
+                            path.display()
+                        )
+                        .into());
                     }
-
-                    // The address of the instruction in the code section
-                    let address: i64 = {
-                        let mut addr: i64 = row.address().try_into()?;
-                        if row.end_sequence() {
-                            addr -= 1;
-                        }
-                        addr + offset
-                    };
-
-                    // Determine line/column. DWARF line/column is never 0
-                    let line = {
-                        let line = match row.line() {
-                            Some(line) => line.get(),
-
-                            // No line information means this code block does not belong to
-                            // a source code block (generated by the compiler for whatever
-                            // reason)
-                            None => 0,
-                        };
-                        line.try_into()?
-                    };
-
-                    let column: i64 = {
-                        let col = match row.column() {
-                            gimli::ColumnType::LeftEdge => 1,
-                            gimli::ColumnType::Column(column) => column.get(),
-                        };
-                        col.try_into()?
-                    };
-
-                    let point = CodePoint {
-                        path,
-                        address,
-                        line,
-                        column,
-                    };
-
-                    points.insert(point.address, point);
                 }
+
+                read_points(&debug_object, offset)?
             }
-        }
+            None => read_points(&object, offset)?,
+        };
 
         Ok(Self {
             path,
             points,
+            files,
+            functions,
+            tombstoned_sequences,
+            units,
+            modified,
             sourcemap_size,
+            debug_id,
+            compressed,
+            debug_path,
+            missing_debug_path,
         })
     }
 
+    /// The debug ID of the WASM, which ties it to the sourcemaps generated
+    /// from it (as the `debugId` of the sourcemap), formatted as a UUID.
+    ///
+    /// It's taken from the `build_id` custom section when the WASM has one
+    /// (see [`WASM::add_build_id`]), otherwise it's derived from the code
+    /// section, so it stays the same as long as the code does.
+    pub fn debug_id(&self) -> String {
+        debug_id::format(&self.debug_id)
+    }
+
+    /// Checks if 'debug_id' (i.e. sent along with a stack trace) is the debug
+    /// ID of the WASM, so the offsets are not resolved against the wrong
+    /// build. The case and the dashes of the UUID don't matter.
+    pub fn verify_debug_id(&self, debug_id: &str) -> Result<(), Error> {
+        let expected = self.debug_id();
+        if debug_id::matches(debug_id, &expected) {
+            Ok(())
+        } else {
+            Err(format!(
+                "Debug ID mismatch, expected {} for {}, got {}",
+                expected,
+                self.path.display(),
+                debug_id
+            )
+            .into())
+        }
+    }
+
+    /// Checks if the 'sourcemap' JSON was generated from this WASM by
+    /// comparing its `debugId` to the debug ID of the WASM. Sourcemaps
+    /// without a `debugId` can't be verified, so they are refused as well.
+    pub fn verify_map(&self, sourcemap: &str) -> Result<(), Error> {
+        let sourcemap = json::decode(sourcemap)?;
+        match sourcemap.get("debugId") {
+            Some(json::Value::String(debug_id)) => self.verify_debug_id(debug_id),
+            _ => Err("The sourcemap has no debugId to verify".into()),
+        }
+    }
+
     /// Generate the sourcemap v3 JSON from the parsed WASM DWARF data.
     ///
     /// The `bundle` parameter, when set to true, bundles the source code
     /// of your project in the source map, so you can jump to the source
-    /// code from the console, not just the raw WASM bytecode.
+    /// code from the console, not just the raw WASM bytecode. Source code
+    /// embedded in the DWARF info (i.e. built with clang's `-gembed-source`)
+    /// is preferred over reading the source files from the disk.
     ///
     /// Note: The mapper is currently not able to package the source code
     /// of crate dependencies, nor the rust library sources.
@@ -263,45 +391,63 @@ impl WASM {
     ///     ],
     ///     "mappings": {
     ///         "yjBAiIA,qCAIiB,QAMhB,...,oBAAA"
-    ///     }
+    ///     },
+    ///     "debugId": "85314830-023f-3cf1-a267-535f4e37bb17"
     /// }
     /// ```
     pub fn map_v3(&self, bundle: bool) -> String {
-        let mut sourcemap = String::with_capacity(self.points.len() * 4 + 100);
+        let options = MapOptions::new().bundle(bundle);
+
+        // The default options only warn about stale sources, so this can't fail
+        self.map_v3_with(&options)
+            .expect("Failed to generate the sourcemap")
+    }
+
+    /// Generate the sourcemap v3 JSON from the parsed WASM DWARF data with
+    /// the given [`MapOptions`] (see [`WASM::map_v3`] for details).
+    ///
+    /// Returns an error if a bundled source file is stale and the
+    /// [`StaleSources::Fail`] policy is set.
+    pub fn map_v3_with(&self, options: &MapOptions) -> Result<String, Error> {
         let Generated {
             mappings,
             sources,
             contents,
-        } = self.generate(bundle);
-
-        sourcemap.push('{');
-        sourcemap.push_str(r#""version":3,"#);
-        sourcemap.push_str(r#""names":[],"#);
-        let processed_sources: Vec<String> = sources
-            .into_iter()
-            .map(|source| {
-                if let Some(pos) = source.find(':') {
-                    source[pos + 1..].to_string()
-                } else {
-                    source
-                }
-            })
-            .map(|source| source.replace('\\', "/"))
-            .collect();
-        sourcemap
-            .push_str(format!(r#""sources":["{}"],"#, processed_sources.join(r#"",""#)).as_str());
+            names,
+            ignore_list,
+        } = self.generate(options)?;
+        let string = |value: &str| format!(r#""{}""#, json::encode(value));
+
+        let mut members = vec![
+            ("version", json::Member::Value("3".to_owned())),
+            (
+                "names",
+                json::Member::Array(names.iter().map(|name| string(name)).collect()),
+            ),
+        ];
+        if let Some(source_root) = &options.source_root {
+            members.push(("sourceRoot", json::Member::Value(string(source_root))));
+        }
+        members.push((
+            "sources",
+            json::Member::Array(sources.iter().map(|source| string(source)).collect()),
+        ));
 
         if let Some(contents) = contents {
-            debug_assert!(bundle);
-            sourcemap.push_str(format!(r#""sourcesContent":[{}],"#, contents.join(",")).as_str());
+            debug_assert!(options.bundle);
+            members.push(("sourcesContent", json::Member::Array(contents)));
         } else {
-            sourcemap.push_str(r#""sourcesContent":null,"#);
+            members.push(("sourcesContent", json::Member::Value("null".to_owned())));
+        }
+        if !ignore_list.is_empty() {
+            let ignore_list = ignore_list.iter().map(usize::to_string).collect();
+            members.push(("ignoreList", json::Member::Array(ignore_list)));
         }
 
-        sourcemap.push_str(format!(r#""mappings":"{}""#, mappings.join(",")).as_str());
-        sourcemap.push('}');
+        members.push(("mappings", json::Member::Value(string(&mappings.join(",")))));
+        members.push(("debugId", json::Member::Value(string(&self.debug_id()))));
 
-        sourcemap
+        Ok(json::object(&members, options.pretty))
     }
 
     #[allow(rustdoc::invalid_html_tags)]
@@ -330,6 +476,7 @@ impl WASM {
     ///
     /// More details in the [WebAssembly Module Specification](https://webassembly.github.io/spec/core/binary/modules.html)
     pub fn patch(&mut self, url: &str) -> Result<(), Error> {
+        self.check_uncompressed()?;
         // Open WASM binary for writing
         let mut wasm = fs::OpenOptions::new()
             .write(true)
@@ -359,21 +506,10 @@ impl WASM {
 
         // Generate the souceMappingURL custom
         // section (see above for info on structure)
-        const WASM_CUSTOM_SECTION_ID: u32 = 0;
-        let section_name = "sourceMappingURL";
-        let section_content = [
-            &vlq::encode_uint_var(section_name.len() as u32)[..],
-            section_name.as_bytes(),
-            &vlq::encode_uint_var(url.len() as u32)[..],
-            url.as_bytes(),
-        ]
-        .concat();
-        let section = [
-            &vlq::encode_uint_var(WASM_CUSTOM_SECTION_ID)[..],
-            &vlq::encode_uint_var(section_content.len() as u32)[..],
-            section_content.as_ref(),
-        ]
-        .concat();
+        let section = section::encode_custom(
+            "sourceMappingURL",
+            &[&vlq::encode_uint_var(url.len() as u64)[..], url.as_bytes()].concat(),
+        );
 
         // Write out the custom section
         wasm.write_all(&section)
@@ -387,20 +523,374 @@ impl WASM {
         Ok(())
     }
 
+    /// Patch the loaded WASM file to reference the 'sourcemap' JSON inlined
+    /// as a `data:application/json;base64,...` URL (see [`WASM::patch`]),
+    /// so the browser needs no separate sourcemap file, i.e. for single file
+    /// demos and test harnesses. The WASM grows by a third more than the
+    /// size of the sourcemap.
+    pub fn patch_inline(&mut self, sourcemap: &str) -> Result<(), Error> {
+        self.patch(&url::data_url(sourcemap))
+    }
+
+    /// Stores the 'sourcemap' JSON as is in a `sourceMap` custom section of
+    /// the loaded WASM file, replacing the one stored before (if any). The
+    /// browsers don't look at this section, but tools can read the sourcemap
+    /// back with [`WASM::embedded_map`] without a separate file.
+    pub fn embed_map(&mut self, sourcemap: &str) -> Result<(), Error> {
+        self.check_uncompressed()?;
+        let raw = fs::read(&self.path)?;
+        let sections = section::read(&raw)?;
+
+        let section = section::encode_custom(EMBEDDED_MAP_SECTION, sourcemap.as_bytes());
+        self.rewrite(
+            &raw,
+            &sections,
+            |section| section.name != Some(EMBEDDED_MAP_SECTION),
+            &section,
+        )
+    }
+
+    /// Lists the sections of the WASM file in the order they appear in it,
+    /// with their offset and size in the (decompressed) file
+    pub fn sections(&self) -> Result<Vec<SectionInfo>, Error> {
+        let raw = read_module(&self.path)?;
+
+        Ok(section::read(raw.deref())?
+            .iter()
+            .map(|section| SectionInfo {
+                id: section.id,
+                name: section
+                    .name
+                    .unwrap_or_else(|| info::section_name(section.id))
+                    .to_owned(),
+                offset: section.range.start as u64,
+                size: section.range.len() as u64,
+            })
+            .collect())
+    }
+
+    /// Reads the URL in the `sourceMappingURL` section of the WASM file, the
+    /// browsers load the sourcemap from, or None if it has no such section
+    pub fn source_mapping_url(&self) -> Result<Option<String>, Error> {
+        let raw = read_module(&self.path)?;
+        let sections = section::read(raw.deref())?;
+
+        Ok(source_mapping_url(&raw, &sections)?.map(str::to_owned))
+    }
+
+    /// Reads the tools and languages the WASM was produced with from the
+    /// `producers` custom section, or an empty list if it has no such section
+    pub fn producers(&self) -> Result<Vec<Producer>, Error> {
+        let raw = read_module(&self.path)?;
+
+        match section::read(raw.deref())?
+            .iter()
+            .find(|section| section.name == Some("producers"))
+        {
+            Some(section) => info::read_producers(&raw[section.payload.clone()]),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// The compilation units of the DWARF info, with their DWARF version
+    /// and the compiler they were compiled with
+    pub fn compilation_units(&self) -> &[CompilationUnit] {
+        &self.units
+    }
+
+    /// The separate file the DWARF info was read from (see
+    /// [`WASM::load_with_debug_info`]), or None if it's in the WASM itself
+    pub fn debug_file(&self) -> Option<&Path> {
+        self.debug_path.as_deref()
+    }
+
+    /// The companion debug file referenced by the `external_debug_info`
+    /// section of the WASM, if it doesn't exist, so the WASM was loaded
+    /// without DWARF info (see [`WASM::load`])
+    pub fn missing_debug_file(&self) -> Option<&Path> {
+        self.missing_debug_path.as_deref()
+    }
+
+    /// Reads back the sourcemap JSON embedded in the WASM file, either in
+    /// the `sourceMap` custom section (see [`WASM::embed_map`]) or inlined
+    /// as a data URL in the sourceMappingURL section (see
+    /// [`WASM::patch_inline`]), or None if the sourcemap is not embedded.
+    pub fn embedded_map(&self) -> Result<Option<String>, Error> {
+        let raw = read_module(&self.path)?;
+        let sections = section::read(raw.deref())?;
+
+        if let Some(section) = sections
+            .iter()
+            .find(|section| section.name == Some(EMBEDDED_MAP_SECTION))
+        {
+            return str::from_utf8(&raw[section.payload.clone()])
+                .map(|sourcemap| Some(sourcemap.to_owned()))
+                .map_err(|_| "The embedded sourcemap is not valid UTF-8".into());
+        }
+
+        match source_mapping_url(&raw, &sections)? {
+            Some(url) => url::parse_data_url(url).transpose(),
+            None => Ok(None),
+        }
+    }
+
+    /// Adds a `build_id` custom section with the debug ID to the loaded WASM
+    /// file (unless it already has one), so the debug ID no longer depends
+    /// on the code and survives tools rewriting the code section, i.e.
+    /// wasm-opt. The debug ID itself doesn't change.
+    pub fn add_build_id(&mut self) -> Result<(), Error> {
+        self.check_uncompressed()?;
+        let raw = fs::read(&self.path)?;
+        let sections = section::read(&raw)?;
+
+        if sections
+            .iter()
+            .any(|section| section.name == Some(debug_id::BUILD_ID_SECTION))
+        {
+            return Ok(());
+        }
+
+        let build_id = section::encode_custom(
+            debug_id::BUILD_ID_SECTION,
+            &[&vlq::encode_uint_var(16)[..], &self.debug_id].concat(),
+        );
+        self.rewrite(&raw, &sections, |_| true, &build_id)
+    }
+
+    /// Moves the DWARF debug info out of the loaded WASM file into the
+    /// separate file under 'debug_path' (usually `<name>.debug.wasm`) and
+    /// references it from the WASM via an `external_debug_info` custom section
+    /// pointing to 'url'.
+    ///
+    /// The debug file is a valid WASM module with the same code as the
+    /// original, so it can be used by [`WASM::load_with_debug_info`] and by
+    /// debuggers following the `external_debug_info` section, like the Chrome
+    /// DWARF debugging extension. The DWARF info already loaded is kept, so
+    /// the sourcemap can still be generated after splitting.
+    pub fn split_debug_info(
+        &mut self,
+        debug_path: impl AsRef<Path>,
+        url: &str,
+    ) -> Result<(), Error> {
+        self.check_uncompressed()?;
+        let raw = fs::read(&self.path)?;
+        let sections = section::read(&raw)?;
+
+        if !sections.iter().any(Section::is_dwarf) {
+            return Err("The WASM file has no DWARF debug info to split".into());
+        }
+
+        // The debug file gets all the standard sections (so the code can be
+        // matched) and the DWARF sections, but no other custom sections
+        let mut debug = section::header(&raw).to_vec();
+        for section in sections
+            .iter()
+            .filter(|section| section.id != section::CUSTOM_SECTION_ID || section.is_dwarf())
+        {
+            debug.extend_from_slice(&raw[section.range.clone()]);
+        }
+        fs::write(debug_path.as_ref(), debug).map_err(|err| {
+            format!(
+                "Failed to write debug info file {}: {}",
+                debug_path.as_ref().display(),
+                err
+            )
+        })?;
+
+        let external_debug_info = section::encode_custom(
+            "external_debug_info",
+            &[&vlq::encode_uint_var(url.len() as u64)[..], url.as_bytes()].concat(),
+        );
+        self.rewrite(
+            &raw,
+            &sections,
+            |section| !section.is_dwarf() && section.name != Some("external_debug_info"),
+            &external_debug_info,
+        )
+    }
+
+    /// Removes all DWARF custom sections from the loaded WASM file, and when
+    /// 'strip_names' is set, the `name` section with the function names too.
+    ///
+    /// The DWARF info is usually many times larger than the code itself, so
+    /// this is meant to be called after generating the sourcemap to get a
+    /// small release build. The sourceMappingURL section (if any) is kept, so
+    /// the browser still finds the sourcemap, and the DWARF info already
+    /// loaded stays available for generating the sourcemap.
+    pub fn strip_debug(&mut self, strip_names: bool) -> Result<(), Error> {
+        self.check_uncompressed()?;
+        let raw = fs::read(&self.path)?;
+        let sections = section::read(&raw)?;
+
+        self.rewrite(
+            &raw,
+            &sections,
+            |section| !(section.is_dwarf() || strip_names && section.name == Some("name")),
+            &[],
+        )
+    }
+
+    // The compressed WASM files are only read, writing them back would need
+    // recompressing the whole file, which is better done after the changes
+    fn check_uncompressed(&self) -> Result<(), Error> {
+        if self.compressed {
+            return Err(format!(
+                "The WASM file {} is compressed, modify the uncompressed file and compress it afterwards",
+                self.path.display()
+            )
+            .into());
+        }
+
+        Ok(())
+    }
+
+    // Writes the WASM file back without the sections for which 'keep' returns
+    // false and with the 'append' bytes added as new sections. The
+    // sourceMappingURL section (if any) is kept as the last one, so patch()
+    // can still update it.
+    //
+    // Sections before the code section are never removed, as that would
+    // shift the code addresses the loaded DWARF info refers to.
+    fn rewrite(
+        &mut self,
+        raw: &[u8],
+        sections: &[Section],
+        keep: impl Fn(&Section) -> bool,
+        append: &[u8],
+    ) -> Result<(), Error> {
+        let code_start = sections
+            .iter()
+            .find(|section| section.id == section::CODE_SECTION_ID)
+            .map(|section| section.range.start)
+            .ok_or("Missing code section in WASM")?;
+        if sections
+            .iter()
+            .any(|section| section.range.start < code_start && !keep(section))
+        {
+            return Err("Cannot remove sections preceding the code section of the WASM".into());
+        }
+
+        let sourcemap = self
+            .sourcemap_size
+            .and_then(|_| sections.last())
+            .map(|section| &raw[section.range.clone()]);
+
+        let mut wasm = section::header(raw).to_vec();
+        let count = sections.len() - usize::from(sourcemap.is_some());
+        for section in sections[..count].iter().filter(|section| keep(section)) {
+            wasm.extend_from_slice(&raw[section.range.clone()]);
+        }
+        wasm.extend_from_slice(append);
+        if let Some(sourcemap) = sourcemap {
+            wasm.extend_from_slice(sourcemap);
+        }
+
+        fs::write(&self.path, wasm).map_err(|err| format!("Failed to write WASM file: {}", err))?;
+
+        Ok(())
+    }
+
     // Generate the sourcemap mappings and source ids.
     //
     // The sourcemap 3 format tries to save on file size by using offsets
     // wherever possible. So we need to encode the source file data and
     // line, column data for each WASM code segment address in the expected
     // order, so offsets make sense when resolved by the browser (or debugger)
-    fn generate<'a>(&'a self, bundle: bool) -> Generated {
+    fn generate<'a>(&'a self, options: &'a MapOptions) -> Result<Generated, Error> {
+        // The rows of the mappings after the transforms (see MapOptions),
+        // with None marking the code which is mapped to nothing: the code of
+        // the source files left out and the rows dropped by the transforms
+        let mut rows: Vec<(u64, Option<Row>)> = Vec::new();
+
+        // Whether the source files pass the include/exclude filters
+        let mut included: HashMap<&'a Path, bool> = HashMap::new();
+
+        // The function names are only looked up if anything needs them
+        let with_functions = options.names || !options.transforms.is_empty();
+
+        for point in self.points.values() {
+            // Line 0 means that this is an intermediate code block and does not
+            // refer to a code block in the source files. We need to skip these
+            // in order to generate the proper offset encoding
+            if point.line == 0 {
+                continue;
+            }
+
+            let is_included = *included
+                .entry(&point.path)
+                .or_insert_with(|| filter::is_included(&self.classify(&point.path), options));
+            if !is_included {
+                rows.push((point.address, None));
+                continue;
+            }
+
+            let row = Row {
+                address: point.address,
+                path: point.path.clone(),
+                line: point.line,
+                column: point.column,
+                function: match with_functions {
+                    true => self
+                        .function_at(point.address)
+                        .map(|function| function.name.clone()),
+                    false => None,
+                },
+            };
+            let transformed = options
+                .transforms
+                .iter()
+                .fold(vec![row], |rows, transform| {
+                    rows.into_iter()
+                        .flat_map(|row| transform.row(row))
+                        .collect()
+                });
+            if transformed.is_empty() {
+                rows.push((point.address, None));
+            }
+            rows.extend(transformed.into_iter().map(|row| (row.address, Some(row))));
+        }
+
+        // The transforms can move the rows around, but the mappings have to
+        // be in the order of the addresses for the offsets to make sense
+        if !options.transforms.is_empty() {
+            rows.sort_by_key(|(address, _)| *address);
+        }
+
         // We collect all referenced source code files in a table and use the
-        // source id (which is the value param of this HashMap) as the basis for
-        // the offset when encoding position (i.e. last source id - this source id),
-        // which require preserving the order of inserts!
-        let mut sources: Vec<&'a Path> = Vec::new();
-        //let mut sources: BTreeMap<&'a Path, i64> = BTreeMap::new();
-        //let mut sources: HashMap<&'a Path, i64> = HashMap::new();
+        // index in it as the source id, which is encoded as an offset from the
+        // last source id, so the order of the inserts has to be preserved.
+        // The ids are None for the source files dropped by the transforms.
+        let mut ids: HashMap<&Path, Option<usize>> = HashMap::new();
+        let mut sources: Vec<SourceEntry> = Vec::new();
+        for row in rows.iter().filter_map(|(_, row)| row.as_ref()) {
+            if !ids.contains_key(row.path.as_path()) {
+                let id = self
+                    .source_entry(&row.path, options, options.bundle)?
+                    .map(|source| {
+                        sources.push(source);
+                        sources.len() - 1
+                    });
+                ids.insert(&row.path, id);
+            }
+        }
+
+        // Canonical maps don't depend on which address references a file
+        // first, the source table is sorted by the URLs
+        if options.canonical {
+            let mut order = (0..sources.len()).collect::<Vec<_>>();
+            order.sort_by(|&a, &b| {
+                (&sources[a].url, &sources[a].path).cmp(&(&sources[b].url, &sources[b].path))
+            });
+            let mut new_ids = vec![0; sources.len()];
+            for (new_id, &id) in order.iter().enumerate() {
+                new_ids[id] = new_id;
+            }
+            for id in ids.values_mut().flatten() {
+                *id = new_ids[*id];
+            }
+            let mut sorted = sources.into_iter().map(Some).collect::<Vec<_>>();
+            sources = order.iter().filter_map(|&id| sorted[id].take()).collect();
+        }
 
         // This is the WASM address -> file:line:col mapping table in the
         // required format, which is basically offsets written after each other
@@ -414,75 +904,1127 @@ impl WASM {
         let mut last_line: i64 = 1;
         let mut last_column: i64 = 1;
 
-        for line in self.points.values() {
-            // Line 0 means that this is an intermediate code block and does not
-            // refer to a code block in the source files. We need to skip these
-            // in order to generate the proper offset encoding
-            if line.line == 0 {
-                continue;
+        // The source files split to lines when the columns are translated,
+        // or None if the source file is not available, by the source ids
+        let mut lines: HashMap<usize, Option<SourceLines<'a>>> = HashMap::new();
+
+        // Whether the last segment is an unmapped one
+        let mut unmapped = false;
+
+        // The function names referenced by the first mapping of each function
+        // (if the names are requested), the same way as the sources
+        let mut names: Vec<&str> = Vec::new();
+        let mut name_ids: HashMap<&str, i64> = HashMap::new();
+        let mut last_name_id: i64 = 0;
+        let mut named_function: Option<&str> = None;
+
+        // NOTE: WASM::load() guarantees that all positions are in the
+        // sourcemap range, but the rows from the transforms have to be checked
+        for (address, row) in &rows {
+            if *address > MAX_SOURCEMAP_POSITION {
+                return Err(format!("Address {} is out of the sourcemap range", address).into());
             }
+            let address = *address as i64;
+            let mapped = row
+                .as_ref()
+                .filter(|row| row.line != 0)
+                .and_then(|row| Some((row, ids[row.path.as_path()]?)));
 
-            // We either get the id of a source file if already in the table
-            // or we get the max(id) + 1 as the new id for a previously unseen
-            // source file, which we promptly insert into the source table
+            // The unmapped code gets a segment of only the address, so it's
+            // not attributed to the previous mapped source line
+            let (row, source_id) = match mapped {
+                Some(mapped) => mapped,
+                None => {
+                    if !unmapped {
+                        mappings.push(vlq::encode(address - last_address));
+                        last_address = address;
+                        unmapped = true;
+                    }
+                    continue;
+                }
+            };
+            unmapped = false;
 
-            let source_id: i64 =
-                if let Some(id) = sources.iter().position(|&val| val == line.path.as_path()) {
-                    id as i64
-                } else {
-                    let id = sources.len() as i64;
-                    sources.push(&line.path);
-                    id
-                };
+            let column = if options.columns == ColumnMode::Raw {
+                row.column
+            } else {
+                lines
+                    .entry(source_id)
+                    .or_insert_with(|| self.source_lines(&row.path, options))
+                    .as_ref()
+                    .and_then(|lines| lines.line(row.line))
+                    .map(|text| column::to_utf16(text, row.column, options.columns))
+                    .unwrap_or(row.column)
+                    // Surrogate pairs can push a huge character column over
+                    .min(MAX_SOURCEMAP_POSITION)
+            };
+            if row.line > MAX_SOURCEMAP_POSITION || column > MAX_SOURCEMAP_POSITION {
+                return Err(format!(
+                    "Line {} column {} of {} is out of the sourcemap range",
+                    row.line,
+                    column,
+                    row.path.display()
+                )
+                .into());
+            }
+            let source_id = source_id as i64;
+            let line_number = row.line as i64;
+            let column = column as i64;
 
             // Calculate the offsets (see above)
-            let address_delta = line.address - last_address;
+            let address_delta = address - last_address;
             let source_id_delta = source_id - last_source_id;
-            let line_delta = line.line - last_line;
-            let column_delta = line.column - last_column;
+            let line_delta = line_number - last_line;
+            let column_delta = column - last_column;
 
             // Store the mapping offsets in the specific format
             // (see above) in the mapping table
-            let mapping = format!(
+            let mut mapping = format!(
                 "{}{}{}{}",
                 vlq::encode(address_delta).as_str(),
                 vlq::encode(source_id_delta).as_str(),
                 vlq::encode(line_delta).as_str(),
                 vlq::encode(column_delta).as_str()
             );
+
+            // The name of the function goes to its first mapping
+            if let (true, Some(name)) = (options.names, row.function.as_deref()) {
+                if named_function != Some(name) {
+                    named_function = Some(name);
+                    let name_id = *name_ids.entry(name).or_insert_with(|| {
+                        names.push(name);
+                        names.len() as i64 - 1
+                    });
+                    mapping.push_str(vlq::encode(name_id - last_name_id).as_str());
+                    last_name_id = name_id;
+                }
+            }
             mappings.push(mapping);
 
             // Update the tracking variables to the freshly calculated values
             // to use them in the next iteration (see above)
-            last_address = line.address;
+            last_address = address;
             last_source_id = source_id;
-            last_line = line.line;
-            last_column = line.column;
+            last_line = line_number;
+            last_column = column;
         }
 
-        // We only need the file paths from the sources table in the order
-        // they were encoded, turned to strings
-        let sources = sources
-            .iter()
-            .filter_map(|p| Some(p.as_os_str().to_str()?.to_owned()))
-            .collect::<Vec<_>>();
-
-        let contents = bundle.then(|| {
+        let contents = options.bundle.then(|| {
             sources
                 .iter()
-                .map(Path::new)
-                .map(|path| {
-                    fs::read_to_string(path)
-                        .map(|content| Cow::Owned(format!(r#""{}""#, json::encode(&content))))
-                        .unwrap_or(Cow::Borrowed("null"))
+                .map(|source| match &source.content {
+                    Some(content) => format!(r#""{}""#, json::encode(content)),
+                    None => "null".to_owned(),
                 })
                 .collect()
         });
 
-        Generated {
+        let ignore_list = sources
+            .iter()
+            .enumerate()
+            .filter(|(_, source)| source.ignored)
+            .map(|(index, _)| index)
+            .collect::<Vec<_>>();
+
+        Ok(Generated {
             mappings,
-            sources,
             contents,
+            names: names.into_iter().map(str::to_owned).collect(),
+            ignore_list,
+            sources: sources.into_iter().map(|source| source.url).collect(),
+        })
+    }
+
+    // The source file under 'path' as it goes to the sourcemap, with the
+    // content read if 'content' is set, after the transforms (or None if a
+    // transform dropped it)
+    fn source_entry(
+        &self,
+        path: &Path,
+        options: &MapOptions,
+        content: bool,
+    ) -> Result<Option<SourceEntry>, Error> {
+        let source = SourceEntry {
+            path: path.to_owned(),
+            url: source_url(path, options),
+            content: match content {
+                true => self.read_source(path, options)?.map(Cow::into_owned),
+                false => None,
+            },
+            ignored: filter::is_ignored(&self.classify(path), options),
+        };
+
+        Ok(options
+            .transforms
+            .iter()
+            .try_fold(source, |source, transform| transform.source(source)))
+    }
+
+    // The function the code at 'address' is in, if the DWARF info has it
+    fn function_at(&self, address: u64) -> Option<&Function> {
+        self.functions
+            .range(..=address)
+            .next_back()
+            .map(|(_, function)| function)
+            .filter(|function| address < function.end)
+    }
+
+    /// Lists the source files referenced by the DWARF info, classified by
+    /// where they come from: the workspace, a registry or git dependency or
+    /// the standard library (see [`Source::classify`]). The workspace root is
+    /// the compilation directory of the DWARF unit referencing the file.
+    pub fn sources(&self) -> Vec<Source> {
+        self.files.keys().map(|path| self.classify(path)).collect()
+    }
+
+    /// Writes a tar archive with everything needed to symbolicate the code
+    /// of the WASM later, without the original checkout, to 'writer':
+    /// * `manifest.json` with the debug ID of the WASM and the files of the
+    ///   archive (see below)
+    /// * the WASM itself and the separate DWARF info file (if any), i.e.
+    ///   `myproject.wasm` and `myproject.debug.wasm`
+    /// * the sourcemap generated with 'options', i.e. `myproject.wasm.map`
+    /// * the source files mapped by the sourcemap which can be read (or are
+    ///   embedded in the DWARF info) under `sources/`, with the path of their
+    ///   URL in the sourcemap, i.e. `sources/home/me/myproject/src/lib.rs`
+    ///
+    /// The manifest is in the form of
+    /// `{"debugId":"85314830-023f-3cf1-a267-535f4e37bb17","module":"myproject.wasm",
+    /// "debugFile":"myproject.debug.wasm","map":"myproject.wasm.map",
+    /// "sources":[{"path":"/home/me/myproject/src/lib.rs","url":"...","file":"sources/..."}],
+    /// "missingSources":["..."]}`, with `debugFile` being `null` if the DWARF
+    /// info is in the WASM.
+    pub fn bundle(&self, writer: impl Write, options: &MapOptions) -> Result<(), Error> {
+        let sourcemap = self.map_v3_with(options)?;
+
+        // The compressed WASM files are bundled decompressed
+        let name = match self.path.extension() {
+            Some(extension) if self.compressed && (extension == "gz" || extension == "br") => {
+                self.path.file_stem()
+            }
+            _ => self.path.file_name(),
         }
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "module.wasm".to_owned());
+        let map = format!("{}.map", name);
+        let debug_file = self.debug_path.as_ref().map(|debug_path| {
+            debug_path
+                .file_name()
+                .map(|debug_name| debug_name.to_string_lossy().into_owned())
+                .filter(|debug_name| *debug_name != name)
+                .unwrap_or_else(|| "module.debug.wasm".to_owned())
+        });
+
+        // The source files of the sourcemap under the path of their URL
+        let mut paths = self
+            .points
+            .values()
+            .filter(|point| point.line != 0)
+            .map(|point| point.path.as_path())
+            .collect::<Vec<_>>();
+        paths.sort();
+        paths.dedup();
+        let mut sources = BTreeMap::new();
+        let mut missing = Vec::new();
+        for path in paths {
+            if !filter::is_included(&self.classify(path), options) {
+                continue;
+            }
+            let source = match self.source_entry(path, options, true)? {
+                Some(source) => source,
+                None => continue,
+            };
+            match source.content {
+                Some(content) => {
+                    let file = format!("sources/{}", url::archive_path(&source.url));
+                    sources.entry(file).or_insert((path, source.url, content));
+                }
+                None => missing.push(path),
+            }
+        }
+
+        let manifest = format!(
+            r#"{{"debugId":"{}","module":"{}","debugFile":{},"map":"{}","sources":[{}],"missingSources":[{}]}}"#,
+            self.debug_id(),
+            json::encode(&name),
+            debug_file
+                .as_ref()
+                .map(|debug_file| format!(r#""{}""#, json::encode(debug_file)))
+                .unwrap_or_else(|| "null".to_owned()),
+            json::encode(&map),
+            sources
+                .iter()
+                .map(|(file, (path, url, _))| {
+                    format!(
+                        r#"{{"path":"{}","url":"{}","file":"{}"}}"#,
+                        json::encode(&path.to_string_lossy()),
+                        json::encode(url),
+                        json::encode(file)
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join(","),
+            missing
+                .iter()
+                .map(|path| format!(r#""{}""#, json::encode(&path.to_string_lossy())))
+                .collect::<Vec<_>>()
+                .join(",")
+        );
+
+        let mut tar = tar::TarWriter::new(writer);
+        tar.append("manifest.json", manifest.as_bytes())?;
+        tar.append(&name, &read_module(&self.path)?)?;
+        if let (Some(debug_path), Some(debug_file)) = (&self.debug_path, &debug_file) {
+            tar.append(debug_file, &read_module(debug_path)?)?;
+        }
+        tar.append(&map, sourcemap.as_bytes())?;
+        for (file, (_, _, content)) in &sources {
+            tar.append(file, content.as_bytes())?;
+        }
+        tar.finish()?;
+
+        Ok(())
+    }
+
+    /// Attributes the bytes of the code section to the crates, source files
+    /// and functions they were compiled from, to see what takes up space in
+    /// the WASM.
+    ///
+    /// The function names come from the DWARF info, or the `name` section
+    /// for the functions without debug info. The code not covered by the
+    /// DWARF line programs (or compiled from line 0) is attributed to
+    /// `<unknown>`.
+    pub fn size_report<'a>(&'a self) -> Result<SizeReport, Error> {
+        let mut crates = SizeTable::default();
+        let mut files = SizeTable::default();
+        let mut functions = SizeTable::default();
+        let mut total = 0;
+
+        // The file and crate names of the source files
+        let mut sources: HashMap<&'a Path, (String, String)> = HashMap::new();
+
+        self.walk_code(|function, point, size| {
+            total += size;
+            functions.add(function, size);
+
+            match point.filter(|point| point.line != 0) {
+                Some(point) => {
+                    let (file, crate_name) = sources.entry(&point.path).or_insert_with(|| {
+                        (
+                            point.path.display().to_string(),
+                            self.crate_name(&point.path),
+                        )
+                    });
+                    files.add(file, size);
+                    crates.add(crate_name, size);
+                }
+                None => {
+                    files.add(size::UNKNOWN, size);
+                    crates.add(size::UNKNOWN, size);
+                }
+            }
+        })?;
+
+        Ok(SizeReport {
+            total,
+            crates: crates.into_entries(),
+            files: files.into_entries(),
+            functions: functions.into_entries(),
+        })
+    }
+
+    /// Checks how well the DWARF info covers the code, to find out why
+    /// stepping through the code or mapping errors in the browser is spotty:
+    /// how many code bytes of each crate and function are covered by line
+    /// info, how many line 0 (compiler generated code) rows and tombstoned
+    /// (dead code) sequences the line programs have and which source files
+    /// can't be found or read for bundling.
+    pub fn quality_report(&self) -> Result<QualityReport, Error> {
+        self.quality_report_with(&MapOptions::default())
+    }
+
+    /// Same as [`Self::quality_report`], but checks the source files the way
+    /// a sourcemap generated with the 'options' would read them, i.e. from
+    /// the [`MapOptions::source_provider`], or only the embedded ones for a
+    /// [`MapOptions::canonical`] map.
+    pub fn quality_report_with<'a>(&'a self, options: &MapOptions) -> Result<QualityReport, Error> {
+        let mut crates = CoverageTable::default();
+        let mut functions = CoverageTable::default();
+        let mut crate_names: HashMap<&'a Path, String> = HashMap::new();
+
+        self.walk_code(|function, point, size| {
+            let crate_name = match point {
+                Some(point) => crate_names
+                    .entry(&point.path)
+                    .or_insert_with(|| self.crate_name(&point.path))
+                    .as_str(),
+                None => size::UNKNOWN,
+            };
+
+            functions.add(function, point, size);
+            crates.add(crate_name, point, size);
+        })?;
+
+        let mut unresolved_sources = Vec::new();
+        let mut unreadable_sources = Vec::new();
+        let mut stale_sources = Vec::new();
+        for (path, file) in &self.files {
+            if path.as_os_str().is_empty() || self.embedded_source(path).is_some() {
+                continue;
+            }
+
+            let provider = match options.sources() {
+                Some(provider) => provider,
+                None => {
+                    unresolved_sources.push(path.clone());
+                    continue;
+                }
+            };
+
+            match provider.source(path) {
+                Some(content) => {
+                    if let Some(reason) = self.stale_reason(path, Some(file), &content, provider) {
+                        stale_sources.push((path.clone(), reason.to_string()));
+                    }
+                }
+                // Only the files on the disk can tell why they can't be read
+                None if options.source_provider.is_none() => {
+                    match fs::read(path).map(String::from_utf8) {
+                        Ok(Ok(_)) => {}
+                        Ok(Err(_)) => {
+                            unreadable_sources.push((path.clone(), "not valid UTF-8".into()))
+                        }
+                        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                            unresolved_sources.push(path.clone())
+                        }
+                        Err(err) => unreadable_sources.push((path.clone(), err.to_string())),
+                    }
+                }
+                None => unresolved_sources.push(path.clone()),
+            }
+        }
+
+        let functions = functions.into_entries();
+        Ok(QualityReport {
+            total: functions.iter().map(|function| function.size).sum(),
+            covered: functions.iter().map(|function| function.covered).sum(),
+            line_zero_rows: functions
+                .iter()
+                .map(|function| function.line_zero_rows)
+                .sum(),
+            tombstoned_sequences: self.tombstoned_sequences,
+            crates: crates.into_entries(),
+            functions,
+            unresolved_sources,
+            unreadable_sources,
+            stale_sources,
+        })
+    }
+
+    /// Looks up the source location of the code at 'offset' in the WASM
+    /// module (i.e. `0x1a2b` in the `wasm-function[42]:0x1a2b` frames of the
+    /// browser stack traces), or None if it's not covered by the DWARF info.
+    ///
+    /// Compiler generated code (line 0) is attributed to the previous source
+    /// line of the same function.
+    pub fn lookup(&self, offset: u64) -> Option<Location> {
+        let function = self
+            .functions
+            .range(..=offset)
+            .next_back()
+            .filter(|(_, function)| offset < function.end);
+
+        // Without knowing where the function starts, only the nearest row is
+        // safe to use, anything earlier might be in another function. If the
+        // DWARF info has the functions, the offset must be in one of them.
+        let (_, point) = match function {
+            Some((&start, _)) => self
+                .points
+                .range(start..=offset)
+                .rev()
+                .find(|(_, point)| point.line != 0),
+            None if !self.functions.is_empty() => None,
+            None => self
+                .points
+                .range(..=offset)
+                .next_back()
+                .filter(|(_, point)| point.line != 0),
+        }?;
+
+        Some(Location {
+            function: function.map(|(_, function)| function.name.clone()),
+            path: point.path.clone(),
+            line: point.line,
+            column: point.column,
+        })
+    }
+
+    /// Symbolicates a stack trace of the WASM from V8 (Chrome, Node.js),
+    /// SpiderMonkey (Firefox) or wasmtime by replacing each WASM frame with
+    /// the Rust function and source location, i.e. the
+    /// `at wasm-function[42]:0x1a2b` frame with
+    /// `at my_app::run (src/lib.rs:12:5)`. Other lines and the frames which
+    /// can't be resolved are left as they are.
+    pub fn symbolicate(&self, trace: &str) -> String {
+        trace
+            .lines()
+            .map(|line| {
+                match symbolicate::frame_offset(line).and_then(|offset| self.lookup(offset)) {
+                    Some(location) => {
+                        let indent = &line[..line.len() - line.trim_start().len()];
+                        format!("{}at {}", indent, location)
+                    }
+                    None => line.to_owned(),
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    // Walks the function bodies in the code section and calls 'visit' with
+    // the name of the function, the line program row covering the next range
+    // of code in it (each row covers the code up to the next row, the code
+    // before the first row of the function is not covered by any) and the
+    // size of the range
+    fn walk_code<'a>(
+        &'a self,
+        mut visit: impl FnMut(&str, Option<&'a CodePoint>, u64),
+    ) -> Result<(), Error> {
+        let raw = read_module(&self.path)?;
+        let sections = section::read(raw.deref())?;
+
+        let code = sections
+            .iter()
+            .find(|section| section.id == section::CODE_SECTION_ID)
+            .ok_or("Missing code section in WASM")?;
+        let imported = match sections
+            .iter()
+            .find(|section| section.id == section::IMPORT_SECTION_ID)
+        {
+            Some(imports) => section::imported_functions(&raw, imports)?,
+            None => 0,
+        };
+        let names = sections
+            .iter()
+            .find(|section| section.name == Some("name"))
+            .map(|names| section::function_names(&raw, names))
+            .unwrap_or_default();
+
+        let bodies = section::function_bodies(&raw, code)?;
+        for (index, body) in (imported..).zip(bodies) {
+            let (start, end) = (body.start as u64, body.end as u64);
+
+            // The function names come from the DWARF info or the name section
+            let name = match self.functions.range(start..end).next() {
+                Some((_, function)) => Cow::Borrowed(function.name.as_str()),
+                None => match names.get(&index) {
+                    Some(name) => Cow::Borrowed(name.as_str()),
+                    None => Cow::Owned(format!("func[{}]", index)),
+                },
+            };
+
+            let mut last = (start, None);
+            for (&address, point) in self.points.range(start..end) {
+                visit(&name, last.1, address - last.0);
+                last = (address, Some(point));
+            }
+            visit(&name, last.1, end - last.0);
+        }
+
+        Ok(())
     }
+
+    // The name of the crate the source file under 'path' belongs to
+    fn crate_name(&self, path: &Path) -> String {
+        self.classify(path)
+            .crate_name
+            .unwrap_or_else(|| size::UNKNOWN.into())
+    }
+
+    fn classify(&self, path: &Path) -> Source {
+        let comp_dir = self
+            .files
+            .get(path)
+            .and_then(|file| file.comp_dir.as_deref());
+
+        Source::classify(path, comp_dir)
+    }
+
+    // The source code compiled from 'path' embedded in the DWARF info
+    fn embedded_source(&self, path: &Path) -> Option<&str> {
+        self.files.get(path)?.content.as_deref()
+    }
+
+    // Loads the source code under 'path' split to lines for translating the
+    // columns, preferring the source embedded in the DWARF info. Canonical
+    // maps only use the embedded source, as the files on the disk can differ
+    // between machines.
+    fn source_lines<'a>(&'a self, path: &Path, options: &'a MapOptions) -> Option<SourceLines<'a>> {
+        let content = match self.embedded_source(path) {
+            Some(content) => Cow::Borrowed(content),
+            None => options.sources()?.source(path)?,
+        };
+
+        Some(SourceLines::new(content))
+    }
+
+    // Loads the source code under 'path' for bundling, preferring the source
+    // embedded in the DWARF info, or None if it's not available (or stale and
+    // the stale sources policy says to skip it, or not embedded for a
+    // canonical map)
+    fn read_source<'a>(
+        &'a self,
+        path: &Path,
+        options: &'a MapOptions,
+    ) -> Result<Option<Cow<'a, str>>, Error> {
+        let file = self.files.get(path);
+        let stale = options.stale_sources;
+        let provider = match options.sources() {
+            Some(provider) => provider,
+            None => return Ok(self.embedded_source(path).map(Cow::Borrowed)),
+        };
+
+        // The embedded source is exactly what was compiled, no need to check
+        let content = match self.embedded_source(path) {
+            Some(content) => Cow::Borrowed(content),
+            None => match provider.source(path) {
+                Some(content) => {
+                    let reason = match stale {
+                        StaleSources::Ignore => None,
+                        _ => self.stale_reason(path, file, &content, provider),
+                    };
+                    if let Some(reason) = reason {
+                        match stale {
+                            StaleSources::Ignore => unreachable!(),
+                            StaleSources::Skip => return Ok(None),
+                            StaleSources::Warn => {
+                                if let Some(handler) = &options.on_stale_source {
+                                    handler.stale_source(path, reason);
+                                }
+                            }
+                            StaleSources::Fail => {
+                                return Err(format!(
+                                    "Source file {} is stale, {}",
+                                    path.display(),
+                                    reason
+                                )
+                                .into())
+                            }
+                        }
+                    }
+
+                    content
+                }
+                None => return Ok(None),
+            },
+        };
+
+        Ok(Some(content))
+    }
+
+    // Checks if the source file changed since the build against the MD5
+    // checksum in the DWARF info or, without one, the modification time of
+    // the file the DWARF info was read from, and returns the reason if so
+    fn stale_reason(
+        &self,
+        path: &Path,
+        file: Option<&SourceFile>,
+        content: &str,
+        provider: &dyn SourceProvider,
+    ) -> Option<&'static str> {
+        match file.and_then(|file| file.md5) {
+            Some(md5) => (md5::digest(content.as_bytes()) != md5)
+                .then_some("its MD5 checksum differs from the one in the DWARF info"),
+            None => {
+                let built = self.modified?;
+                let modified = provider.modified(path)?;
+                (modified > built).then_some("it was modified after the WASM was built")
+            }
+        }
+    }
+}
+
+// The WASM binary in memory
+enum RawModule {
+    #[cfg(feature = "memmap2")]
+    Mapped(memmap2::Mmap),
+    #[cfg(not(feature = "memmap2"))]
+    Read(Vec<u8>),
+    // The WASM file is compressed, this is the decompressed binary
+    Decompressed(Vec<u8>),
+}
+
+impl Deref for RawModule {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            #[cfg(feature = "memmap2")]
+            Self::Mapped(raw) => raw,
+            #[cfg(not(feature = "memmap2"))]
+            Self::Read(raw) => raw,
+            Self::Decompressed(raw) => raw,
+        }
+    }
+}
+
+// The URL of the source file under 'path' in the sourcemap, with the prefix
+// remapped and, for canonical maps, the `..` components resolved
+fn source_url(path: &Path, options: &MapOptions) -> String {
+    let path = options.remap(path);
+    match options.canonical {
+        true => url::source_url(&url::normalize(&path)),
+        false => url::source_url(&path),
+    }
+}
+
+// The URL in the sourceMappingURL section of the WASM binary, if any
+fn source_mapping_url<'a>(raw: &'a [u8], sections: &[Section]) -> Result<Option<&'a str>, Error> {
+    match sections
+        .iter()
+        .find(|section| section.name == Some("sourceMappingURL"))
+    {
+        Some(section) => {
+            let (url, _) = section::read_name(raw, section.payload.start)?;
+            str::from_utf8(url)
+                .map(Some)
+                .map_err(|_| "Malformed sourceMappingURL section in WASM".into())
+        }
+        None => Ok(None),
+    }
+}
+
+// Reads the WASM binary under 'path' into memory, decompressing it if the
+// file is gzip compressed
+fn read_module(path: &Path) -> Result<RawModule, Error> {
+    #[cfg(feature = "memmap2")]
+    let raw = {
+        // Load the WASM file into memory via mmap to speed things up
+        // with large WASM files
+        let file = fs::File::open(path)?;
+        RawModule::Mapped(unsafe { memmap2::Mmap::map(&file) }?)
+    };
+    #[cfg(not(feature = "memmap2"))]
+    let raw = {
+        // Load the WASM file via the standard library, which can be slower
+        // for larger WASM files, but some platforms might not be supported
+        // by memmap2
+        RawModule::Read(fs::read(path)?)
+    };
+
+    match compress::decompress(path, &raw)? {
+        Some(wasm) => Ok(RawModule::Decompressed(wasm)),
+        None => Ok(raw),
+    }
+}
+
+// Returns the raw bytes of the code section of the WASM
+fn code_section<'a>(object: &object::File<'a>) -> Result<&'a [u8], Error> {
+    Ok(object
+        .section_by_index(object::SectionIndex(DWARF_CODE_SECTION_ID))?
+        .data()?)
+}
+
+// Resolves the companion debug file referenced by the external_debug_info
+// custom section of the WASM (if any), which might not exist.
+//
+// The section holds a single (length prefixed) URL, which is usually relative
+// to the WASM file. For absolute http(s) URLs we look for the file with the
+// same name next to the WASM file, as that is how it is usually deployed.
+fn external_debug_info(object: &object::File, path: &Path) -> Result<Option<PathBuf>, Error> {
+    let data = match object.section_by_name("external_debug_info") {
+        Some(section) => section.data()?,
+        None => return Ok(None),
+    };
+
+    let url = vlq::decode_uint_var(data)
+        .and_then(|(length, start)| {
+            let end = start.checked_add(usize::try_from(length).ok()?)?;
+            str::from_utf8(data.get(start..end)?).ok()
+        })
+        .ok_or("Malformed external_debug_info section in WASM")?;
+
+    let relative = if let Some(file) = url.strip_prefix("file://") {
+        file
+    } else if url.contains("://") {
+        url.rsplit('/').next().unwrap_or(url)
+    } else {
+        url
+    };
+
+    let mut debug_path = path.parent().map(Path::to_owned).unwrap_or_default();
+    debug_path.push(relative);
+
+    Ok(Some(debug_path))
+}
+
+type DwarfReader<'a> = gimli::EndianSlice<'a, gimli::RunTimeEndian>;
+
+// Builds the full path of a file entry of a line program header.
+//
+// The directory with index 0 is the compilation directory itself: DWARF 4
+// leaves it implicit (gimli substitutes DW_AT_comp_dir), while DWARF 5 stores
+// it as the first entry of the directory table, and both can be relative (i.e.
+// when the paths are remapped), so it must not be joined to the compilation
+// directory again. Any other relative directory is relative to the
+// compilation directory. DWARF 5 file index 0 (the primary source file) and
+// DW_FORM_line_strp strings are resolved by gimli.
+fn file_path(
+    dwarf: &gimli::Dwarf<DwarfReader>,
+    unit: &gimli::Unit<DwarfReader>,
+    header: &gimli::LineProgramHeader<DwarfReader>,
+    file: &gimli::FileEntry<DwarfReader>,
+) -> Result<PathBuf, Error> {
+    let attr_path =
+        |attr| -> Result<PathBuf, Error> { Ok(bytes_to_path(&dwarf.attr_string(unit, attr)?)) };
+    let comp_dir = comp_dir(dwarf, unit, header)?;
+
+    let mut path = PathBuf::new();
+    match file.directory(header) {
+        Some(dir) if file.directory_index() != 0 => {
+            let dir = attr_path(dir)?;
+
+            // Relative directories are relative to the compilation unit directory.
+            if dir.is_relative() {
+                if let Some(comp_dir) = comp_dir {
+                    path.push(comp_dir);
+                }
+            }
+
+            path.push(dir);
+        }
+        _ => {
+            if let Some(comp_dir) = comp_dir {
+                path.push(comp_dir);
+            }
+        }
+    }
+
+    // An absolute file path replaces the directory entirely
+    path.push(attr_path(file.path_name())?);
+
+    // Drop the `.` components, so the same file referenced from different
+    // units (i.e. as `./src/lib.rs` and `src/lib.rs`) has the same path
+    Ok(path
+        .components()
+        .filter(|component| component != &std::path::Component::CurDir)
+        .collect())
+}
+
+// The compilation directory of the unit, which is the first entry of the
+// directory table since DWARF 5
+fn comp_dir(
+    dwarf: &gimli::Dwarf<DwarfReader>,
+    unit: &gimli::Unit<DwarfReader>,
+    header: &gimli::LineProgramHeader<DwarfReader>,
+) -> Result<Option<PathBuf>, Error> {
+    Ok(match header.directory(0) {
+        Some(dir) if header.version() >= 5 => Some(bytes_to_path(&dwarf.attr_string(unit, dir)?)),
+        _ => unit.comp_dir.map(|dir| bytes_to_path(&dir)),
+    })
+}
+
+// Paths in the DWARF info are raw bytes, which are kept as is where the
+// platform allows it, so non-UTF-8 paths survive until the sourcemap
+#[cfg(unix)]
+fn bytes_to_path(bytes: &[u8]) -> PathBuf {
+    use std::os::unix::ffi::OsStrExt;
+
+    PathBuf::from(std::ffi::OsStr::from_bytes(bytes))
+}
+
+#[cfg(not(unix))]
+fn bytes_to_path(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(bytes).as_ref())
+}
+
+// Collects the source file info from a file entry of a line program header
+fn source_file(
+    dwarf: &gimli::Dwarf<DwarfReader>,
+    unit: &gimli::Unit<DwarfReader>,
+    header: &gimli::LineProgramHeader<DwarfReader>,
+    file: &gimli::FileEntry<DwarfReader>,
+) -> Result<SourceFile, Error> {
+    // LLVM emits the embedded source for every file if any of them has it,
+    // so an empty string means there is no source for this one
+    let content = match file.source() {
+        Some(source) => Some(
+            dwarf
+                .attr_string(unit, source)?
+                .to_string_lossy()
+                .into_owned(),
+        )
+        .filter(|content| !content.is_empty()),
+        None => None,
+    };
+
+    let md5 = header.file_has_md5().then(|| *file.md5());
+    let comp_dir = comp_dir(dwarf, unit, header)?;
+
+    Ok(SourceFile {
+        content,
+        md5,
+        comp_dir,
+    })
+}
+
+// Reads the DWARF line programs of the WASM and translates them to code
+// points with the code section 'offset' applied to their addresses, and
+// collects the source file info for the referenced files
+fn read_points(object: &object::File, offset: u64) -> Result<DebugInfo, Error> {
+    // Load all of the DWARF sections
+    let section =
+        gimli::DwarfSections::load(|id: gimli::SectionId| -> Result<Cow<[u8]>, gimli::Error> {
+            match object.section_by_name(id.name()) {
+                Some(ref section) => Ok(section
+                    .uncompressed_data()
+                    .unwrap_or(Cow::Borrowed(&[][..]))),
+                None => Ok(Cow::Borrowed(&[][..])),
+            }
+        })?;
+
+    // Borrow a `Cow<[u8]>` to create an `EndianSlice`.
+    let borrow_section: &dyn for<'a> Fn(
+        &'a Cow<[u8]>,
+    ) -> gimli::EndianSlice<'a, gimli::RunTimeEndian> =
+        &|section| gimli::EndianSlice::new(section, gimli::RunTimeEndian::Little);
+
+    // Create `EndianSlice`s for all of the sections.
+    let dwarf = section.borrow(&borrow_section);
+
+    // Collect the debug data and enforce that they are sorted by address
+    // which BTreeMap guarantees
+    let mut points: BTreeMap<u64, CodePoint> = BTreeMap::new();
+    let mut files: BTreeMap<PathBuf, SourceFile> = BTreeMap::new();
+    let mut functions: BTreeMap<u64, Function> = BTreeMap::new();
+    let mut tombstoned_sequences = 0;
+    let mut units = Vec::new();
+
+    let mut iter = dwarf.units();
+    while let Some(header) = iter.next()? {
+        let unit = dwarf.unit(header)?;
+        units.push(compilation_unit(&dwarf, &unit)?);
+        read_functions(&dwarf, &unit, offset, &mut functions)?;
+
+        // Get the line program for the compilation unit.
+        if let Some(program) = unit.line_program.clone() {
+            tombstoned_sequences += count_tombstones(program.header())?;
+
+            // Iterate over the line program rows for the unit.
+            let mut rows = program.rows();
+            while let Some((header, row)) = rows.next_row()? {
+                // We will collect the embdedded path from the DWARF loc metadata
+                let path = match row.file(header) {
+                    Some(file) => {
+                        let path = file_path(&dwarf, &unit, header, file)?;
+                        if !files.contains_key(&path) {
+                            files.insert(path.clone(), source_file(&dwarf, &unit, header, file)?);
+                        }
+                        path
+                    }
+                    None => PathBuf::new(),
+                };
+
+                // The address of the instruction in the code section. The
+                // DWARF addresses are 4 bytes for wasm32 and 8 bytes for
+                // wasm64 (memory64) modules, gimli reads both into an u64
+                // and tombstoned (dead code) sequences are already skipped.
+                let address = if row.end_sequence() {
+                    row.address().checked_sub(1)
+                } else {
+                    Some(row.address())
+                }
+                .and_then(|address| address.checked_add(offset))
+                .filter(|&address| address <= MAX_SOURCEMAP_POSITION)
+                .ok_or_else(|| {
+                    format!(
+                        "Code address {:#x} is out of the range of a sourcemap",
+                        row.address()
+                    )
+                })?;
+
+                // Determine line/column. DWARF line/column is never 0
+                let line = match row.line() {
+                    Some(line) => line.get(),
+
+                    // No line information means this code block does not belong to
+                    // a source code block (generated by the compiler for whatever
+                    // reason)
+                    None => 0,
+                };
+
+                let column = match row.column() {
+                    gimli::ColumnType::LeftEdge => 1,
+                    gimli::ColumnType::Column(column) => column.get(),
+                };
+
+                if line > MAX_SOURCEMAP_POSITION || column > MAX_SOURCEMAP_POSITION {
+                    return Err(format!(
+                        "Source position {}:{} is out of the range of a sourcemap",
+                        line, column
+                    )
+                    .into());
+                }
+
+                let point = CodePoint {
+                    path,
+                    address,
+                    line,
+                    column,
+                };
+
+                points.insert(point.address, point);
+            }
+        }
+    }
+
+    Ok(DebugInfo {
+        points,
+        files,
+        functions,
+        tombstoned_sequences,
+        units,
+    })
+}
+
+// Reads the attributes of the root entry of the 'unit' describing it
+fn compilation_unit(
+    dwarf: &gimli::Dwarf<DwarfReader>,
+    unit: &gimli::Unit<DwarfReader>,
+) -> Result<CompilationUnit, Error> {
+    let mut entries = unit.entries();
+    let root = match entries.next_dfs()? {
+        Some((_, root)) => root,
+        None => return Err("Missing compilation unit entry in DWARF info".into()),
+    };
+
+    let producer = match root.attr_value(gimli::DW_AT_producer)? {
+        Some(producer) => Some(
+            dwarf
+                .attr_string(unit, producer)?
+                .to_string_lossy()
+                .into_owned(),
+        ),
+        None => None,
+    };
+    let language = match root.attr_value(gimli::DW_AT_language)? {
+        Some(gimli::AttributeValue::Language(language)) => Some(
+            language
+                .static_string()
+                .map(str::to_owned)
+                .unwrap_or_else(|| format!("{:#x}", language.0)),
+        ),
+        _ => None,
+    };
+
+    Ok(CompilationUnit {
+        version: unit.header.version(),
+        name: unit.name.map(|name| name.to_string_lossy().into_owned()),
+        comp_dir: unit.comp_dir.map(|dir| bytes_to_path(&dir)),
+        producer,
+        language,
+    })
+}
+
+// Counts the sequences of the line program starting at the tombstone address
+// the linker uses for the code it removed, which gimli skips when reading
+// the rows
+fn count_tombstones(header: &gimli::LineProgramHeader<DwarfReader>) -> Result<u64, Error> {
+    let tombstone = match header.address_size() {
+        4 => u64::from(u32::MAX),
+        _ => u64::MAX,
+    };
+
+    let mut count = 0;
+    let mut instructions = header.instructions();
+    while let Some(instruction) = instructions.next_instruction(header)? {
+        if let gimli::LineInstruction::SetAddress(address) = instruction {
+            if address == tombstone {
+                count += 1;
+            }
+        }
+    }
+
+    Ok(count)
+}
+
+// Collects the names of the functions defined in the 'unit', qualified with
+// the namespaces and types they're in (i.e. `my_app::State::new`), keyed by
+// their start address with the code section 'offset' applied
+fn read_functions(
+    dwarf: &gimli::Dwarf<DwarfReader>,
+    unit: &gimli::Unit<DwarfReader>,
+    offset: u64,
+    functions: &mut BTreeMap<u64, Function>,
+) -> Result<(), Error> {
+    // The concrete function entries usually only refer to the declaration or
+    // the abstract instance (for inlined functions) which has the name, and
+    // those might come later in the unit
+    let mut names: HashMap<gimli::UnitOffset, String> = HashMap::new();
+    let mut concrete: Vec<(gimli::Range, gimli::UnitOffset)> = Vec::new();
+
+    // The names of the namespaces and types enclosing the current entry
+    let mut scopes: Vec<Option<String>> = Vec::new();
+    let mut depth: isize = 0;
+
+    let mut entries = unit.entries();
+    while let Some((delta, entry)) = entries.next_dfs()? {
+        depth += delta;
+        scopes.truncate(depth.max(0) as usize);
+
+        let name = match entry.attr_value(gimli::DW_AT_name)? {
+            Some(name) => Some(
+                dwarf
+                    .attr_string(unit, name)?
+                    .to_string_lossy()
+                    .into_owned(),
+            ),
+            None => None,
+        };
+
+        if entry.tag() == gimli::DW_TAG_subprogram {
+            if let Some(name) = &name {
+                let mut qualified = String::new();
+                for scope in scopes.iter().flatten() {
+                    qualified.push_str(scope);
+                    qualified.push_str("::");
+                }
+                qualified.push_str(name);
+                names.insert(entry.offset(), qualified);
+            }
+
+            let mut origin = entry.offset();
+            for attr in [gimli::DW_AT_abstract_origin, gimli::DW_AT_specification] {
+                if let Some(gimli::AttributeValue::UnitRef(offset)) = entry.attr_value(attr)? {
+                    origin = offset;
+                }
+            }
+
+            if let Some(range) = dwarf.die_ranges(unit, entry)?.next()? {
+                concrete.push((range, origin));
+            }
+        }
+
+        let scope = match entry.tag() {
+            gimli::DW_TAG_namespace
+            | gimli::DW_TAG_structure_type
+            | gimli::DW_TAG_enumeration_type
+            | gimli::DW_TAG_union_type => name,
+            _ => None,
+        };
+        scopes.push(scope);
+    }
+
+    for (range, origin) in concrete {
+        let start = range.begin.checked_add(offset);
+        let end = range.end.checked_add(offset);
+        if let (Some(start), Some(end), Some(name)) = (start, end, names.get(&origin)) {
+            functions.entry(start).or_insert_with(|| Function {
+                end,
+                name: name.clone(),
+            });
+        }
+    }
+
+    Ok(())
 }
@@ -0,0 +1,352 @@
+use crate::{error::Error, ColumnMode, FileSystem, SourceKind, SourceProvider, Transform};
+use std::{
+    borrow::Cow,
+    fmt,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+};
+
+/// What to do with a bundled source file which changed since the WASM was
+/// built, so the sourcemap doesn't silently ship the wrong source.
+///
+/// A source file is considered stale if it doesn't match the MD5 checksum
+/// recorded in the DWARF 5 line table or, when there's no checksum, it was
+/// modified after the WASM (or its separate debug info file).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StaleSources {
+    /// Bundle the source files without checking them
+    Ignore,
+    /// Leave stale source files out of the sourcemap (`null` content)
+    Skip,
+    /// Bundle stale source files, but report each of them to
+    /// [`MapOptions::on_stale_source`] (if set)
+    #[default]
+    Warn,
+    /// Fail generating the sourcemap if any source file is stale
+    Fail,
+}
+
+/// Gets told about the stale source files bundled with
+/// [`StaleSources::Warn`], with the path of the source file and the reason
+/// it's considered stale, i.e. to print a warning. It's implemented for the
+/// closures taking the same arguments.
+///
+/// ```rust
+/// use std::path::Path;
+/// use wasm2map::MapOptions;
+///
+/// let options = MapOptions::new()
+///     .bundle(true)
+///     .on_stale_source(|path: &Path, reason: &str| {
+///         eprintln!("warning: bundling stale source file {}, {}", path.display(), reason)
+///     });
+/// ```
+pub trait StaleSourceHandler: Send + Sync {
+    /// Called for each stale source file bundled
+    fn stale_source(&self, path: &Path, reason: &str);
+}
+
+impl<F> StaleSourceHandler for F
+where
+    F: Fn(&Path, &str) + Send + Sync,
+{
+    fn stale_source(&self, path: &Path, reason: &str) {
+        self(path, reason)
+    }
+}
+
+impl fmt::Debug for dyn StaleSourceHandler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("StaleSourceHandler")
+    }
+}
+
+impl FromStr for StaleSources {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "ignore" => Ok(Self::Ignore),
+            "skip" => Ok(Self::Skip),
+            "warn" => Ok(Self::Warn),
+            "fail" => Ok(Self::Fail),
+            _ => Err(format!(
+                "Unknown stale sources policy '{}', expected one of ignore, skip, warn or fail",
+                value
+            )
+            .into()),
+        }
+    }
+}
+
+/// Options for generating the sourcemap with [`crate::WASM::map_v3_with`]
+///
+/// The source files left out by the include/exclude globs and crate filters
+/// don't show up in the sourcemap, the code compiled from them is mapped to
+/// nothing instead. The crate and kind of a source file is guessed from its
+/// path (see [`crate::Source::classify`]).
+///
+/// The defaults generate the same sourcemap as [`crate::WASM::map_v3`], the
+/// options can be changed field by field or with the builder methods:
+///
+/// ```rust
+/// use wasm2map::{ColumnMode, MapOptions, SourceKind};
+///
+/// let options = MapOptions::new()
+///     .bundle(true)
+///     .columns(ColumnMode::Chars)
+///     .remap_path_prefix("/home/me/myproject", "myproject")
+///     .ignore_kind(SourceKind::Std);
+/// assert!(options.bundle);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct MapOptions {
+    /// Bundle the source code in the sourcemap (`sourcesContent`)
+    pub bundle: bool,
+    /// What to do with bundled sources which changed since the build
+    pub stale_sources: StaleSources,
+    /// Gets told about the stale sources bundled with [`StaleSources::Warn`],
+    /// nothing is reported if not set
+    pub on_stale_source: Option<Arc<dyn StaleSourceHandler>>,
+    /// How to translate the DWARF columns to the UTF-16 sourcemap columns
+    pub columns: ColumnMode,
+    /// Generate the same sourcemap for the same WASM on any machine, for
+    /// reproducible builds and caching: the `..` components of the source
+    /// paths are resolved, the sources are sorted by their URL and nothing is
+    /// read from the disk, so only source code embedded in the DWARF info (or
+    /// supplied by the `source_provider`) is bundled or used to translate
+    /// the columns
+    pub canonical: bool,
+    /// Only map the source files matching any of these globs (all of them if
+    /// empty), where `*` and `?` match within a directory and `**` across
+    /// directories, i.e. `**/my-app/src/**`
+    pub include: Vec<String>,
+    /// Don't map the source files matching any of these globs
+    pub exclude: Vec<String>,
+    /// Only map the source files of these crates (all of them if empty)
+    pub crates: Vec<String>,
+    /// Don't map the source files of these crates
+    pub exclude_crates: Vec<String>,
+    /// Only map the source files of these kinds (all of them if empty), i.e.
+    /// only [`SourceKind::Workspace`] to leave out the dependencies
+    pub kinds: Vec<SourceKind>,
+    /// The `sourceRoot` of the sourcemap, which the browser prepends to the
+    /// URLs of the sources, i.e. `https://example.com/src/`
+    pub source_root: Option<String>,
+    /// Replace the prefix (first) of the source paths with another (second)
+    /// in the sourcemap, like `--remap-path-prefix` of rustc, i.e. to hide
+    /// the build machine's directories. The last matching one wins.
+    pub remap_path_prefix: Vec<(PathBuf, PathBuf)>,
+    /// Put the source files matching any of these globs on the `ignoreList`
+    /// of the sourcemap, so the debuggers skip them when stepping through the
+    /// code and hide them in the stack traces
+    pub ignore: Vec<String>,
+    /// Put the source files of these kinds on the `ignoreList` of the
+    /// sourcemap, i.e. [`SourceKind::Std`] and [`SourceKind::Registry`] to
+    /// only step through the code of the workspace
+    pub ignore_kinds: Vec<SourceKind>,
+    /// Indent the sourcemap JSON to make it human readable
+    pub pretty: bool,
+    /// Add the names of the functions to the `names` of the sourcemap, and
+    /// reference them from the first mapping of each function, so the
+    /// browsers can show the Rust names in the stack traces
+    pub names: bool,
+    /// Where to read the source files from when they're not embedded in the
+    /// DWARF info, the disk ([`FileSystem`]) if not set
+    pub source_provider: Option<Arc<dyn SourceProvider>>,
+    /// Change the rows of the mappings and the source files in project
+    /// specific ways before writing the sourcemap, in order (see
+    /// [`Transform`])
+    pub transforms: Vec<Arc<dyn Transform>>,
+}
+
+impl MapOptions {
+    /// The default options (see [`crate::WASM::map_v3`])
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets whether to bundle the source code in the sourcemap
+    pub fn bundle(mut self, bundle: bool) -> Self {
+        self.bundle = bundle;
+        self
+    }
+
+    /// Sets what to do with stale bundled sources
+    pub fn stale_sources(mut self, stale_sources: StaleSources) -> Self {
+        self.stale_sources = stale_sources;
+        self
+    }
+
+    /// Sets what to tell about the stale sources bundled with
+    /// [`StaleSources::Warn`]
+    pub fn on_stale_source(mut self, handler: impl StaleSourceHandler + 'static) -> Self {
+        self.on_stale_source = Some(Arc::new(handler));
+        self
+    }
+
+    /// Sets how to translate the DWARF columns
+    pub fn columns(mut self, columns: ColumnMode) -> Self {
+        self.columns = columns;
+        self
+    }
+
+    /// Sets whether to generate a reproducible sourcemap
+    pub fn canonical(mut self, canonical: bool) -> Self {
+        self.canonical = canonical;
+        self
+    }
+
+    /// Adds a glob of the source files to map
+    pub fn include(mut self, glob: impl Into<String>) -> Self {
+        self.include.push(glob.into());
+        self
+    }
+
+    /// Adds a glob of the source files not to map
+    pub fn exclude(mut self, glob: impl Into<String>) -> Self {
+        self.exclude.push(glob.into());
+        self
+    }
+
+    /// Adds a crate to map the source files of
+    pub fn include_crate(mut self, name: impl Into<String>) -> Self {
+        self.crates.push(name.into());
+        self
+    }
+
+    /// Adds a crate not to map the source files of
+    pub fn exclude_crate(mut self, name: impl Into<String>) -> Self {
+        self.exclude_crates.push(name.into());
+        self
+    }
+
+    /// Adds a kind of source files to map
+    pub fn kind(mut self, kind: SourceKind) -> Self {
+        self.kinds.push(kind);
+        self
+    }
+
+    /// Sets the `sourceRoot` of the sourcemap
+    pub fn source_root(mut self, source_root: impl Into<String>) -> Self {
+        self.source_root = Some(source_root.into());
+        self
+    }
+
+    /// Adds a replacement of the 'from' prefix of the source paths with 'to'
+    pub fn remap_path_prefix(mut self, from: impl Into<PathBuf>, to: impl Into<PathBuf>) -> Self {
+        self.remap_path_prefix.push((from.into(), to.into()));
+        self
+    }
+
+    /// Adds a glob of the source files to put on the `ignoreList`
+    pub fn ignore(mut self, glob: impl Into<String>) -> Self {
+        self.ignore.push(glob.into());
+        self
+    }
+
+    /// Adds a kind of source files to put on the `ignoreList`
+    pub fn ignore_kind(mut self, kind: SourceKind) -> Self {
+        self.ignore_kinds.push(kind);
+        self
+    }
+
+    /// Sets whether to indent the sourcemap JSON
+    pub fn pretty(mut self, pretty: bool) -> Self {
+        self.pretty = pretty;
+        self
+    }
+
+    /// Sets whether to add the function names to the sourcemap
+    pub fn names(mut self, names: bool) -> Self {
+        self.names = names;
+        self
+    }
+
+    /// Sets where to read the source files from
+    pub fn source_provider(mut self, provider: impl SourceProvider + 'static) -> Self {
+        self.source_provider = Some(Arc::new(provider));
+        self
+    }
+
+    /// Adds a transform of the mappings, which runs after the ones already
+    /// added
+    pub fn transform(mut self, transform: impl Transform + 'static) -> Self {
+        self.transforms.push(Arc::new(transform));
+        self
+    }
+
+    // The provider of the source files, or None if the sources can only come
+    // from the DWARF info (canonical maps without an explicit provider)
+    pub(crate) fn sources(&self) -> Option<&dyn SourceProvider> {
+        match &self.source_provider {
+            Some(provider) => Some(provider.as_ref()),
+            None if self.canonical => None,
+            None => Some(&FileSystem),
+        }
+    }
+
+    // The source path with the prefix replaced by the last matching
+    // remap_path_prefix (if any)
+    pub(crate) fn remap<'a>(&self, path: &'a Path) -> Cow<'a, Path> {
+        self.remap_path_prefix
+            .iter()
+            .rev()
+            .find_map(|(from, to)| {
+                let rest = path.strip_prefix(from).ok()?;
+                Some(Cow::Owned(match rest.as_os_str().is_empty() {
+                    true => to.clone(),
+                    false => to.join(rest),
+                }))
+            })
+            .unwrap_or(Cow::Borrowed(path))
+    }
+}
+
+/// Options for loading the WASM with [`crate::WASM::load_with`]
+///
+/// ```rust
+/// use wasm2map::LoadOptions;
+///
+/// let options = LoadOptions::new().debug_file("/path/to/the/file.debug.wasm");
+/// assert!(options.debug_file.is_some());
+/// ```
+#[derive(Debug, Clone)]
+pub struct LoadOptions {
+    /// Read the DWARF info from this separate file instead of the WASM (see
+    /// [`crate::WASM::load_with_debug_info`])
+    pub debug_file: Option<PathBuf>,
+    /// Read the DWARF info from the companion file referenced by the
+    /// `external_debug_info` section when the WASM has no DWARF info itself
+    /// (on by default)
+    pub external_debug_info: bool,
+}
+
+impl Default for LoadOptions {
+    fn default() -> Self {
+        Self {
+            debug_file: None,
+            external_debug_info: true,
+        }
+    }
+}
+
+impl LoadOptions {
+    /// The default options (see [`crate::WASM::load`])
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read the DWARF info from the separate file under 'path'
+    pub fn debug_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.debug_file = Some(path.into());
+        self
+    }
+
+    /// Sets whether to follow the `external_debug_info` section
+    pub fn external_debug_info(mut self, external_debug_info: bool) -> Self {
+        self.external_debug_info = external_debug_info;
+        self
+    }
+}
@@ -0,0 +1,186 @@
+use crate::{error::Error, url};
+use std::{
+    borrow::Cow,
+    fmt, fs,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    time::SystemTime,
+};
+
+/// Supplies the content of the source files for the `sourcesContent` of the
+/// bundled sourcemaps (and the column translation), i.e. from a virtual
+/// filesystem, an archive or the sandbox of a build system instead of the
+/// files on the disk.
+///
+/// The source embedded in the DWARF info is always preferred, the provider
+/// is only asked for the rest. The bundled sources are still checked
+/// against the MD5 checksum in the DWARF info (see [`crate::StaleSources`]).
+///
+/// ```rust
+/// use std::{borrow::Cow, collections::HashMap, path::{Path, PathBuf}};
+/// use wasm2map::{MapOptions, SourceProvider};
+///
+/// struct InMemory(HashMap<PathBuf, String>);
+///
+/// impl SourceProvider for InMemory {
+///     fn source(&self, path: &Path) -> Option<Cow<'_, str>> {
+///         self.0.get(path).map(|content| Cow::Borrowed(content.as_str()))
+///     }
+/// }
+///
+/// let options = MapOptions::new()
+///     .bundle(true)
+///     .source_provider(InMemory(HashMap::new()));
+/// ```
+pub trait SourceProvider: Send + Sync {
+    /// The content of the source file compiled from 'path' (as it's recorded
+    /// in the DWARF info), or None if it's not available
+    fn source(&self, path: &Path) -> Option<Cow<'_, str>>;
+
+    /// The time the source file under 'path' was last modified, to tell if
+    /// it changed since the build when the DWARF info has no checksum of it.
+    /// None (the default) skips the check.
+    fn modified(&self, _path: &Path) -> Option<SystemTime> {
+        None
+    }
+}
+
+impl fmt::Debug for dyn SourceProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SourceProvider")
+    }
+}
+
+/// Reads the source files from the disk, the default [`SourceProvider`]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FileSystem;
+
+impl SourceProvider for FileSystem {
+    fn source(&self, path: &Path) -> Option<Cow<'_, str>> {
+        fs::read_to_string(path).ok().map(Cow::Owned)
+    }
+
+    fn modified(&self, path: &Path) -> Option<SystemTime> {
+        fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+    }
+}
+
+/// Reads the source files as they are in a commit of a local git repository,
+/// so the bundled sources match what was compiled even after the working
+/// tree moved on (i.e. mapping a release build in CI).
+///
+/// The relative source paths (i.e. with the compilation directory remapped
+/// to `.`) are resolved against the directory the provider was created
+/// with, which is where the WASM was built. The files outside of the
+/// repository (i.e. the standard library) are not available. The `git`
+/// command has to be installed.
+///
+/// ```no_run
+/// use wasm2map::{GitRevision, MapOptions};
+///
+/// let sources = GitRevision::new("/home/me/myproject", "v1.2.3").expect("Unknown revision");
+/// let options = MapOptions::new().bundle(true).source_provider(sources);
+/// ```
+#[derive(Debug, Clone)]
+pub struct GitRevision {
+    // The root of the working tree as reported by git, with the symlinks
+    // resolved
+    root: PathBuf,
+    // The directory the relative source paths are relative to
+    base: PathBuf,
+    // The hash of the commit the revision resolved to
+    commit: String,
+}
+
+impl GitRevision {
+    /// Reads the sources from the commit 'revision' (i.e. a hash, tag or
+    /// branch) of the git repository containing the 'repository' directory,
+    /// which the relative source paths are relative to.
+    ///
+    /// The revision is resolved to a commit right away, so a branch moving
+    /// on doesn't change the sources mid-way. Returns an error if it's not
+    /// a git repository or the revision doesn't exist.
+    pub fn new(repository: impl AsRef<Path>, revision: &str) -> Result<Self, Error> {
+        let repository = repository.as_ref();
+        let base = fs::canonicalize(repository).map_err(|err| {
+            format!(
+                "Failed to open git repository {}, {}",
+                repository.display(),
+                err
+            )
+        })?;
+        let root = git(&base, &["rev-parse", "--show-toplevel"])?;
+        let root = fs::canonicalize(String::from_utf8_lossy(&root).trim_end())?;
+        let commit = git(
+            &root,
+            &["rev-parse", "--verify", &format!("{}^{{commit}}", revision)],
+        )
+        .map_err(|err| format!("Unknown git revision '{}', {}", revision, err))?;
+
+        Ok(Self {
+            root,
+            base,
+            commit: String::from_utf8_lossy(&commit).trim_end().to_owned(),
+        })
+    }
+
+    /// The hash of the commit the sources are read from
+    pub fn commit(&self) -> &str {
+        &self.commit
+    }
+
+    // The path of the source file relative to the root of the repository,
+    // with `/` separators as git expects
+    fn relative_path(&self, path: &Path) -> Option<String> {
+        let path = url::normalize(&self.base.join(path));
+        let canonical;
+        let relative = match path.strip_prefix(&self.root) {
+            Ok(relative) => relative,
+            // The path can lead to the repository through a symlink
+            Err(_) => {
+                canonical = fs::canonicalize(&path).ok()?;
+                canonical.strip_prefix(&self.root).ok()?
+            }
+        };
+
+        let components = relative
+            .components()
+            .map(|component| component.as_os_str().to_str())
+            .collect::<Option<Vec<_>>>()?;
+        Some(components.join("/"))
+    }
+}
+
+impl SourceProvider for GitRevision {
+    fn source(&self, path: &Path) -> Option<Cow<'_, str>> {
+        let path = self.relative_path(path)?;
+        let content = git(
+            &self.root,
+            &["cat-file", "blob", &format!("{}:{}", self.commit, path)],
+        )
+        .ok()?;
+
+        String::from_utf8(content).ok().map(Cow::Owned)
+    }
+}
+
+// Runs git with 'args' in the 'dir' directory and returns what it printed
+fn git(dir: &Path, args: &[&str]) -> Result<Vec<u8>, Error> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|err| format!("Failed to run git, {}", err))?;
+
+    match output.status.success() {
+        true => Ok(output.stdout),
+        false => Err(String::from_utf8_lossy(&output.stderr)
+            .trim()
+            .to_owned()
+            .into()),
+    }
+}
@@ -0,0 +1,158 @@
+use crate::{json, CodePoint};
+use std::{collections::HashMap, path::PathBuf};
+
+/// How much of the code of a crate or a function is covered by line info
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Coverage {
+    /// The name of the crate or the function, or `<unknown>` for the code
+    /// without a row in the line programs
+    pub name: String,
+    /// The number of bytes in the code section
+    pub size: u64,
+    /// The number of bytes mapped to a source line
+    pub covered: u64,
+    /// The number of line program rows with line 0, which is the code the
+    /// compiler generated without a source line to attribute it to
+    pub line_zero_rows: u64,
+}
+
+impl Coverage {
+    /// The percentage of the code bytes covered by line info
+    pub fn percentage(&self) -> f64 {
+        match self.size {
+            0 => 100.0,
+            size => self.covered as f64 * 100.0 / size as f64,
+        }
+    }
+}
+
+/// The quality of the DWARF info of the WASM (see
+/// [`crate::WASM::quality_report`]), with the crates and functions sorted by
+/// the number of code bytes not covered by line info, the worst first
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct QualityReport {
+    /// The total size of the function bodies in the code section
+    pub total: u64,
+    /// The number of code bytes mapped to a source line
+    pub covered: u64,
+    /// The number of line program rows with line 0
+    pub line_zero_rows: u64,
+    /// The number of line program sequences for code the linker removed
+    pub tombstoned_sequences: u64,
+    /// The line info coverage per crate
+    pub crates: Vec<Coverage>,
+    /// The line info coverage per function
+    pub functions: Vec<Coverage>,
+    /// The source files which don't exist and are not embedded in the DWARF
+    /// info either, so they can't be bundled
+    pub unresolved_sources: Vec<PathBuf>,
+    /// The source files which exist but can't be read for bundling, with the
+    /// reason
+    pub unreadable_sources: Vec<(PathBuf, String)>,
+    /// The source files which changed since the build, with the reason, so
+    /// the bundled content doesn't match the compiled code
+    pub stale_sources: Vec<(PathBuf, String)>,
+}
+
+impl QualityReport {
+    /// The percentage of the code bytes covered by line info
+    pub fn percentage(&self) -> f64 {
+        match self.total {
+            0 => 100.0,
+            total => self.covered as f64 * 100.0 / total as f64,
+        }
+    }
+
+    /// Serializes the report to JSON in the form of
+    /// `{"total":1234,"covered":1000,"lineZeroRows":12,"tombstonedSequences":3,
+    /// "crates":[{"name":"core","size":567,"covered":500,"lineZeroRows":4},...],
+    /// "functions":[...],"unresolvedSources":["..."],
+    /// "unreadableSources":[{"path":"...","reason":"..."}],
+    /// "staleSources":[{"path":"...","reason":"..."}]}`
+    pub fn to_json(&self) -> String {
+        let coverage = |entries: &[Coverage]| {
+            entries
+                .iter()
+                .map(|entry| {
+                    format!(
+                        r#"{{"name":"{}","size":{},"covered":{},"lineZeroRows":{}}}"#,
+                        json::encode(&entry.name),
+                        entry.size,
+                        entry.covered,
+                        entry.line_zero_rows
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+        let unresolved = self
+            .unresolved_sources
+            .iter()
+            .map(|path| format!(r#""{}""#, json::encode(&path.to_string_lossy())))
+            .collect::<Vec<_>>();
+        let with_reason = |entries: &[(PathBuf, String)]| {
+            entries
+                .iter()
+                .map(|(path, reason)| {
+                    format!(
+                        r#"{{"path":"{}","reason":"{}"}}"#,
+                        json::encode(&path.to_string_lossy()),
+                        json::encode(reason)
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+
+        format!(
+            r#"{{"total":{},"covered":{},"lineZeroRows":{},"tombstonedSequences":{},"crates":[{}],"functions":[{}],"unresolvedSources":[{}],"unreadableSources":[{}],"staleSources":[{}]}}"#,
+            self.total,
+            self.covered,
+            self.line_zero_rows,
+            self.tombstoned_sequences,
+            coverage(&self.crates),
+            coverage(&self.functions),
+            unresolved.join(","),
+            with_reason(&self.unreadable_sources),
+            with_reason(&self.stale_sources)
+        )
+    }
+}
+
+// Adds up the coverage of the code ranges by name
+#[derive(Default)]
+pub(crate) struct CoverageTable(HashMap<String, Coverage>);
+
+impl CoverageTable {
+    // Adds the code range of 'size' bytes covered by the line program row
+    // 'point' (if any) to the coverage of 'name'
+    pub(crate) fn add(&mut self, name: &str, point: Option<&CodePoint>, size: u64) {
+        let coverage = match self.0.get_mut(name) {
+            Some(coverage) => coverage,
+            None => self.0.entry(name.to_owned()).or_insert(Coverage {
+                name: name.to_owned(),
+                size: 0,
+                covered: 0,
+                line_zero_rows: 0,
+            }),
+        };
+
+        coverage.size += size;
+        match point {
+            Some(point) if point.line == 0 => coverage.line_zero_rows += 1,
+            Some(_) => coverage.covered += size,
+            None => {}
+        }
+    }
+
+    pub(crate) fn into_entries(self) -> Vec<Coverage> {
+        let mut entries = self.0.into_values().collect::<Vec<_>>();
+        entries.sort_by(|a, b| {
+            (b.size - b.covered)
+                .cmp(&(a.size - a.covered))
+                .then_with(|| b.size.cmp(&a.size))
+                .then_with(|| a.name.cmp(&b.name))
+        });
+        entries
+    }
+}
@@ -0,0 +1,241 @@
+// Minimal reader for the top level section layout of a WASM module.
+//
+// The `object` crate gives us the payload of each section, but not where the
+// section header (id, size and for custom sections the name) starts, which we
+// need to know in order to cut sections out of the binary or replace them.
+use crate::{error::Error, vlq};
+use std::{collections::BTreeMap, ops::Range, str};
+
+const WASM_MAGIC: &[u8] = b"\0asm";
+const WASM_HEADER_SIZE: usize = 8;
+
+pub(crate) const CUSTOM_SECTION_ID: u8 = 0;
+pub(crate) const IMPORT_SECTION_ID: u8 = 2;
+pub(crate) const CODE_SECTION_ID: u8 = 10;
+
+#[derive(Debug)]
+pub(crate) struct Section<'a> {
+    // The section id, 0 for custom sections
+    pub(crate) id: u8,
+    // The name of the section, only custom sections have one
+    pub(crate) name: Option<&'a str>,
+    // The byte range of the whole section, including the header
+    pub(crate) range: Range<usize>,
+    // The byte range of the section contents after the header (and the name
+    // of custom sections)
+    pub(crate) payload: Range<usize>,
+}
+
+// Reads a LEB128 encoded number at `pos` in the module and returns it as an
+// usize together with the position right after it. A 'pos' past the end of
+// the module is malformed too.
+fn read_size(raw: &[u8], pos: usize) -> Result<(usize, usize), Error> {
+    let (value, length) = raw
+        .get(pos..)
+        .and_then(vlq::decode_uint_var)
+        .ok_or_else(|| format!("Malformed LEB128 number at offset {:#x} of WASM", pos))?;
+    let value = usize::try_from(value).map_err(|_| {
+        format!(
+            "Size {} at offset {:#x} of WASM does not fit in memory",
+            value, pos
+        )
+    })?;
+
+    Ok((value, pos + length))
+}
+
+// Lists all sections of the WASM module in the order they appear in the binary
+pub(crate) fn read(raw: &[u8]) -> Result<Vec<Section<'_>>, Error> {
+    if raw.len() < WASM_HEADER_SIZE || &raw[..WASM_MAGIC.len()] != WASM_MAGIC {
+        return Err("Not a WASM module".into());
+    }
+
+    let mut sections = Vec::new();
+    let mut pos = WASM_HEADER_SIZE;
+    while pos < raw.len() {
+        let start = pos;
+        let id = raw[pos];
+        let (size, data_start) = read_size(raw, pos + 1)?;
+        let end = data_start
+            .checked_add(size)
+            .filter(|&end| end <= raw.len())
+            .ok_or_else(|| format!("Section at offset {:#x} overflows the WASM file", start))?;
+
+        let (name, payload_start) = if id == CUSTOM_SECTION_ID {
+            let (length, name_start) = read_size(&raw[..end], data_start)?;
+            let name_end = name_start
+                .checked_add(length)
+                .filter(|&name_end| name_end <= end)
+                .ok_or_else(|| {
+                    format!("Custom section name at offset {:#x} is malformed", start)
+                })?;
+            let name = str::from_utf8(&raw[name_start..name_end])
+                .map_err(|_| format!("Custom section name at offset {:#x} is not UTF-8", start))?;
+            (Some(name), name_end)
+        } else {
+            (None, data_start)
+        };
+
+        sections.push(Section {
+            id,
+            name,
+            range: start..end,
+            payload: payload_start..end,
+        });
+        pos = end;
+    }
+
+    Ok(sections)
+}
+
+impl Section<'_> {
+    // Whether this is one of the .debug_* custom sections holding DWARF info
+    pub(crate) fn is_dwarf(&self) -> bool {
+        self.name
+            .map(|name| name.starts_with(".debug_"))
+            .unwrap_or(false)
+    }
+}
+
+// Returns the module header (magic number and version) of the WASM binary
+pub(crate) fn header(raw: &[u8]) -> &[u8] {
+    &raw[..WASM_HEADER_SIZE]
+}
+
+// Encodes a custom section with the given name and payload
+pub(crate) fn encode_custom(name: &str, data: &[u8]) -> Vec<u8> {
+    let content = [
+        &vlq::encode_uint_var(name.len() as u64)[..],
+        name.as_bytes(),
+        data,
+    ]
+    .concat();
+
+    [
+        &[CUSTOM_SECTION_ID][..],
+        &vlq::encode_uint_var(content.len() as u64)[..],
+        content.as_ref(),
+    ]
+    .concat()
+}
+
+// Reads a length prefixed UTF-8 name (or any byte vector) at 'pos'
+pub(crate) fn read_name(raw: &[u8], pos: usize) -> Result<(&[u8], usize), Error> {
+    let (length, start) = read_size(raw, pos)?;
+    let end = start
+        .checked_add(length)
+        .filter(|&end| end <= raw.len())
+        .ok_or_else(|| format!("Name at offset {:#x} of WASM is malformed", pos))?;
+
+    Ok((&raw[start..end], end))
+}
+
+// Counts the imported functions in the import section, which come before the
+// functions defined in the code section in the function index space
+pub(crate) fn imported_functions(raw: &[u8], section: &Section) -> Result<u64, Error> {
+    let raw = &raw[..section.payload.end];
+    let malformed = |pos| format!("Malformed import at offset {:#x} of WASM", pos);
+    let byte = |pos: usize| raw.get(pos).copied().ok_or_else(|| malformed(pos));
+    // Limits are a flag byte with the minimum and optionally the maximum size
+    let limits = |pos: usize| -> Result<usize, Error> {
+        let flags = byte(pos)?;
+        let (_, pos) = read_size(raw, pos + 1)?;
+        Ok(if flags & 1 != 0 {
+            read_size(raw, pos)?.1
+        } else {
+            pos
+        })
+    };
+
+    let (count, mut pos) = read_size(raw, section.payload.start)?;
+    let mut functions = 0;
+    for _ in 0..count {
+        let (_, module_end) = read_name(raw, pos)?;
+        let (_, field_end) = read_name(raw, module_end)?;
+        pos = match byte(field_end)? {
+            0 => {
+                functions += 1;
+                read_size(raw, field_end + 1)?.1
+            }
+            1 => limits(field_end + 2)?,
+            2 => limits(field_end + 1)?,
+            3 => field_end + 3,
+            4 => read_size(raw, field_end + 2)?.1,
+            _ => return Err(malformed(field_end).into()),
+        };
+    }
+
+    Ok(functions)
+}
+
+// Lists the byte ranges of the function bodies (including their size) in
+// the code section, in the order of the function index space
+pub(crate) fn function_bodies(raw: &[u8], section: &Section) -> Result<Vec<Range<usize>>, Error> {
+    let raw = &raw[..section.payload.end];
+    let (count, mut pos) = read_size(raw, section.payload.start)?;
+
+    let mut bodies = Vec::new();
+    for _ in 0..count {
+        let (size, start) = read_size(raw, pos)?;
+        let end = start
+            .checked_add(size)
+            .filter(|&end| end <= raw.len())
+            .ok_or_else(|| {
+                format!(
+                    "Function body at offset {:#x} overflows the code section",
+                    pos
+                )
+            })?;
+        bodies.push(pos..end);
+        pos = end;
+    }
+
+    Ok(bodies)
+}
+
+// Reads the function names from the `name` custom section, keyed by the
+// function index. Malformed or non-UTF-8 names are left out, as the names are
+// only informational.
+pub(crate) fn function_names(raw: &[u8], section: &Section) -> BTreeMap<u64, String> {
+    const FUNCTION_NAMES_ID: u8 = 1;
+
+    let raw = &raw[..section.payload.end];
+    let mut names = BTreeMap::new();
+    let mut pos = section.payload.start;
+    while pos < raw.len() {
+        let id = raw[pos];
+        let subsection = match read_name(raw, pos + 1) {
+            Ok((_, end)) => pos + 1..end,
+            Err(_) => break,
+        };
+        if id == FUNCTION_NAMES_ID {
+            let _ = read_name_map(raw, subsection.clone(), &mut names);
+        }
+        pos = subsection.end;
+    }
+
+    names
+}
+
+fn read_name_map(
+    raw: &[u8],
+    subsection: Range<usize>,
+    names: &mut BTreeMap<u64, String>,
+) -> Result<(), Error> {
+    let raw = &raw[..subsection.end];
+    let (_, mut pos) = read_size(raw, subsection.start)?;
+    let (count, start) = read_size(raw, pos)?;
+    pos = start;
+    for _ in 0..count {
+        let (index, name_start) = vlq::decode_uint_var(&raw[pos..])
+            .map(|(index, length)| (index, pos + length))
+            .ok_or_else(|| format!("Malformed function index at offset {:#x}", pos))?;
+        let (name, end) = read_name(raw, name_start)?;
+        if let Ok(name) = str::from_utf8(name) {
+            names.insert(index, name.to_owned());
+        }
+        pos = end;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,441 @@
+use crate::{
+    error::Error,
+    json::{self, Value},
+    sourcemap::SourceMap,
+    Location, WASM,
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, PoisonError,
+    },
+    thread,
+    time::Duration,
+};
+
+// The largest request body accepted, which is plenty for a batch of offsets
+const MAX_BODY_SIZE: usize = 16 * 1024 * 1024;
+
+// The longest request line or header line, and the most headers accepted
+const MAX_LINE_LENGTH: usize = 8 * 1024;
+const MAX_HEADERS: usize = 100;
+
+// How long a client can take to send the request or read the response
+// before the connection is dropped
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+// The most connections answered at the same time, the ones over it are
+// refused right away
+const MAX_CONNECTIONS: usize = 64;
+
+/// Symbolicates batches of WASM offsets sent over HTTP for a set of builds,
+/// i.e. for an error collecting backend receiving the raw offsets of the
+/// stack frames from many versions of the same app.
+///
+/// A build is either a WASM file with DWARF info or just the sourcemap
+/// generated from it (see [`WASM::map_v3`]). The sourcemap only knows the
+/// function names with [`crate::MapOptions::names`], and only for the first
+/// offset of each function.
+///
+/// A build is only loaded and parsed on the first request for it, then kept
+/// in memory for the requests to come. Each connection is answered on its
+/// own thread and is dropped if the client doesn't send the request or read
+/// the response in time. Over 64 connections at the same time, the new ones
+/// are refused with `503 Service Unavailable`.
+///
+/// `POST /symbolicate` with `{"build":"app-1.2.3","offsets":[6699,"0x1a2b"]}`
+/// answers with the source location of each offset (or just the offset when
+/// it can't be resolved):
+/// `{"build":"app-1.2.3","frames":[{"offset":6699,"function":"app::run",
+/// "file":"/src/app/src/lib.rs","line":12,"column":5},{"offset":6700}]}`
+///
+/// When the request has the `debugId` of the module the offsets come from,
+/// it's refused with `409 Conflict` unless it's the debug ID of the build
+/// (see [`WASM::debug_id`]), so a stale build never gives wrong lines.
+///
+/// `GET /builds` lists the ids of the builds as `{"builds":["app-1.2.3"]}`.
+///
+/// ```no_run
+/// use std::net::TcpListener;
+/// use wasm2map::SymbolServer;
+///
+/// let mut server = SymbolServer::new();
+/// server.add("app-1.2.3", "/path/to/the/file.wasm");
+///
+/// let listener = TcpListener::bind("127.0.0.1:8080").expect("Failed to bind");
+/// server.serve(&listener).expect("Failed to serve");
+/// ```
+#[derive(Debug, Default)]
+pub struct SymbolServer {
+    builds: BTreeMap<String, Build>,
+    // The builds already requested, keyed by the build id
+    cache: Mutex<HashMap<String, Arc<Symbols>>>,
+}
+
+#[derive(Debug)]
+enum Build {
+    Wasm {
+        path: PathBuf,
+        debug_path: Option<PathBuf>,
+    },
+    Map(PathBuf),
+}
+
+// A loaded build
+#[derive(Debug)]
+enum Symbols {
+    Wasm(WASM),
+    Map(SourceMap),
+}
+
+impl Symbols {
+    fn lookup(&self, offset: u64) -> Option<Location> {
+        match self {
+            Self::Wasm(wasm) => wasm.lookup(offset),
+            Self::Map(sourcemap) => sourcemap.lookup(offset),
+        }
+    }
+
+    fn verify_debug_id(&self, debug_id: &str) -> Result<(), Error> {
+        match self {
+            Self::Wasm(wasm) => wasm.verify_debug_id(debug_id),
+            Self::Map(sourcemap) => sourcemap.verify_debug_id(debug_id),
+        }
+    }
+}
+
+// The HTTP status and the JSON body of a response
+type Response = (&'static str, String);
+
+impl SymbolServer {
+    /// Creates a server without any builds to symbolicate
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the build with the WASM file under 'path', which has the DWARF
+    /// info embedded or references a companion debug file, under the 'id'
+    /// the requests refer to it by
+    pub fn add(&mut self, id: impl Into<String>, path: impl Into<PathBuf>) {
+        let path = path.into();
+        self.insert(
+            id.into(),
+            Build::Wasm {
+                path,
+                debug_path: None,
+            },
+        );
+    }
+
+    /// Adds the build with the WASM file under 'path' and its DWARF info in
+    /// the separate file under 'debug_path' (see
+    /// [`WASM::load_with_debug_info`]) under the 'id' the requests refer to
+    /// it by
+    pub fn add_with_debug_info(
+        &mut self,
+        id: impl Into<String>,
+        path: impl Into<PathBuf>,
+        debug_path: impl Into<PathBuf>,
+    ) {
+        let build = Build::Wasm {
+            path: path.into(),
+            debug_path: Some(debug_path.into()),
+        };
+        self.insert(id.into(), build);
+    }
+
+    /// Adds the build with only the sourcemap under 'path' kept, which was
+    /// generated from the WASM (see [`WASM::map_v3`]), under the 'id' the
+    /// requests refer to it by
+    pub fn add_map(&mut self, id: impl Into<String>, path: impl Into<PathBuf>) {
+        self.insert(id.into(), Build::Map(path.into()));
+    }
+
+    fn insert(&mut self, id: String, build: Build) {
+        self.cache
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&id);
+        self.builds.insert(id, build);
+    }
+
+    /// The ids of the builds the server symbolicates, in order
+    pub fn builds(&self) -> impl Iterator<Item = &str> {
+        self.builds.keys().map(String::as_str)
+    }
+
+    /// Answers the requests coming in on 'listener' until accepting a
+    /// connection fails, each connection on its own thread. A connection
+    /// failing (i.e. the client hanging up) only drops that request.
+    pub fn serve(&self, listener: &TcpListener) -> Result<(), Error> {
+        let connections = AtomicUsize::new(0);
+        thread::scope(|scope| loop {
+            let (stream, _) = listener.accept()?;
+            if connections.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+                connections.fetch_sub(1, Ordering::SeqCst);
+                let busy = error("503 Service Unavailable", "Too many connections");
+                let _ = stream
+                    .set_write_timeout(Some(TIMEOUT))
+                    .map_err(Error::from)
+                    .and_then(|_| write_response(&stream, busy));
+                continue;
+            }
+
+            let connections = &connections;
+            scope.spawn(move || {
+                // The client is gone or sent garbage, there's no one to tell
+                let _ = self.serve_connection(stream);
+                connections.fetch_sub(1, Ordering::SeqCst);
+            });
+        })
+    }
+
+    /// Reads an HTTP request from 'stream', answers it and closes the
+    /// connection
+    pub fn serve_connection(&self, stream: TcpStream) -> Result<(), Error> {
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+
+        let mut reader = BufReader::new(&stream);
+        let response = match read_request(&mut reader) {
+            Ok((method, target, body)) => self.respond(&method, &target, &body),
+            Err(response) => response,
+        };
+
+        write_response(&stream, response)
+    }
+
+    fn respond(&self, method: &str, target: &str, body: &[u8]) -> Response {
+        let path = target.split('?').next().unwrap_or(target);
+        match (method, path) {
+            ("POST", "/symbolicate") => {
+                let request = std::str::from_utf8(body)
+                    .map_err(|_| bad_request("The request body is not valid UTF-8".to_owned()))
+                    .and_then(|body| {
+                        json::decode(body).map_err(|err| bad_request(err.to_string()))
+                    });
+                match request {
+                    Ok(request) => self.symbolicate(&request),
+                    Err(response) => response,
+                }
+            }
+            ("GET", "/builds") => {
+                let builds = self
+                    .builds()
+                    .map(|id| format!(r#""{}""#, json::encode(id)))
+                    .collect::<Vec<_>>();
+                ("200 OK", format!(r#"{{"builds":[{}]}}"#, builds.join(",")))
+            }
+            (_, "/symbolicate" | "/builds") => {
+                error("405 Method Not Allowed", "Method not allowed")
+            }
+            _ => error("404 Not Found", "Not found"),
+        }
+    }
+
+    fn symbolicate(&self, request: &Value) -> Response {
+        let id = match request.get("build") {
+            Some(Value::String(id)) => id,
+            _ => return bad_request("The 'build' member must be a string".to_owned()),
+        };
+        let offsets = match request.get("offsets") {
+            Some(Value::Array(offsets)) => offsets,
+            _ => return bad_request("The 'offsets' member must be an array".to_owned()),
+        };
+        let offsets = match offsets.iter().map(parse_offset).collect::<Option<Vec<_>>>() {
+            Some(offsets) => offsets,
+            None => {
+                return bad_request(
+                    "The offsets must be integers or hex strings (i.e. \"0x1a2b\")".to_owned(),
+                )
+            }
+        };
+
+        let symbols = match self.load(id) {
+            Ok(Some(symbols)) => symbols,
+            Ok(None) => return error("404 Not Found", &format!("Unknown build '{}'", id)),
+            Err(err) => {
+                return error(
+                    "500 Internal Server Error",
+                    &format!("Failed to load build '{}', {}", id, err),
+                )
+            }
+        };
+        match request.get("debugId") {
+            Some(Value::String(debug_id)) => {
+                if let Err(err) = symbols.verify_debug_id(debug_id) {
+                    return error("409 Conflict", &err.to_string());
+                }
+            }
+            Some(Value::Null) | None => {}
+            Some(_) => return bad_request("The 'debugId' member must be a string".to_owned()),
+        }
+
+        let frames = offsets
+            .iter()
+            .map(|&offset| frame(offset, symbols.lookup(offset)))
+            .collect::<Vec<_>>();
+
+        (
+            "200 OK",
+            format!(
+                r#"{{"build":"{}","frames":[{}]}}"#,
+                json::encode(id),
+                frames.join(",")
+            ),
+        )
+    }
+
+    // The build, loaded into the cache on the first request. The cache is
+    // not locked while loading, so the other builds can be served meanwhile.
+    fn load(&self, id: &str) -> Result<Option<Arc<Symbols>>, Error> {
+        let build = match self.builds.get(id) {
+            Some(build) => build,
+            None => return Ok(None),
+        };
+
+        let cache = || self.cache.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(symbols) = cache().get(id) {
+            return Ok(Some(symbols.clone()));
+        }
+
+        let symbols = match build {
+            Build::Wasm {
+                path,
+                debug_path: Some(debug_path),
+            } => Symbols::Wasm(WASM::load_with_debug_info(path, debug_path)?),
+            Build::Wasm {
+                path,
+                debug_path: None,
+            } => Symbols::Wasm(WASM::load(path)?),
+            Build::Map(path) => Symbols::Map(SourceMap::parse(&fs::read_to_string(path)?)?),
+        };
+
+        Ok(Some(
+            cache()
+                .entry(id.to_owned())
+                .or_insert_with(|| Arc::new(symbols))
+                .clone(),
+        ))
+    }
+}
+
+// Reads the request line, the headers and the body of an HTTP request
+fn read_request(reader: &mut impl BufRead) -> Result<(String, String, Vec<u8>), Response> {
+    let mut line = String::new();
+    let read_line = |reader: &mut dyn BufRead, line: &mut String| {
+        line.clear();
+        let length = reader
+            .take(MAX_LINE_LENGTH as u64 + 1)
+            .read_line(line)
+            .map_err(|_| bad_request("Malformed request".to_owned()))?;
+        match length > MAX_LINE_LENGTH {
+            true => Err(error(
+                "431 Request Header Fields Too Large",
+                "The request line or a header is too long",
+            )),
+            false => Ok(length),
+        }
+    };
+
+    read_line(reader, &mut line)?;
+    let mut parts = line.split_whitespace();
+    let (method, target) = match (parts.next(), parts.next()) {
+        (Some(method), Some(target)) => (method.to_owned(), target.to_owned()),
+        _ => return Err(bad_request("Malformed request line".to_owned())),
+    };
+
+    let mut length = 0;
+    for headers in 0.. {
+        if headers > MAX_HEADERS {
+            return Err(error(
+                "431 Request Header Fields Too Large",
+                "The request has too many headers",
+            ));
+        }
+        if read_line(reader, &mut line)? == 0 {
+            return Err(bad_request("Unexpected end of the headers".to_owned()));
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                length = value
+                    .trim()
+                    .parse::<usize>()
+                    .map_err(|_| bad_request("Invalid Content-Length".to_owned()))?;
+            }
+        }
+    }
+
+    if length > MAX_BODY_SIZE {
+        return Err(error("413 Payload Too Large", "The request is too large"));
+    }
+    // The body grows as it arrives, not by the length the client claims
+    let mut body = Vec::new();
+    match reader.take(length as u64).read_to_end(&mut body) {
+        Ok(read) if read == length => Ok((method, target, body)),
+        _ => Err(bad_request("Unexpected end of the body".to_owned())),
+    }
+}
+
+fn write_response(mut stream: &TcpStream, (status, body): Response) -> Result<(), Error> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )?;
+    stream.flush()?;
+
+    Ok(())
+}
+
+// Parses an offset sent as a JSON number or a hex string (i.e. "0x1a2b" as
+// it shows up in the stack traces)
+fn parse_offset(value: &Value) -> Option<u64> {
+    match value {
+        Value::Number(number) => number.parse().ok(),
+        Value::String(text) => text
+            .strip_prefix("0x")
+            .or_else(|| text.strip_prefix("0X"))
+            .and_then(|hex| u64::from_str_radix(hex, 16).ok()),
+        _ => None,
+    }
+}
+
+fn frame(offset: u64, location: Option<Location>) -> String {
+    let location = match location {
+        Some(location) => location,
+        None => return format!(r#"{{"offset":{}}}"#, offset),
+    };
+    let function = match &location.function {
+        Some(function) => format!(r#""{}""#, json::encode(function)),
+        None => "null".to_owned(),
+    };
+
+    format!(
+        r#"{{"offset":{},"function":{},"file":"{}","line":{},"column":{}}}"#,
+        offset,
+        function,
+        json::encode(&location.path.to_string_lossy()),
+        location.line,
+        location.column
+    )
+}
+
+fn error(status: &'static str, msg: &str) -> Response {
+    (status, format!(r#"{{"error":"{}"}}"#, json::encode(msg)))
+}
+
+fn bad_request(msg: String) -> Response {
+    error("400 Bad Request", &msg)
+}
@@ -0,0 +1,89 @@
+use crate::json;
+use std::collections::HashMap;
+
+// The name of the group for the code which can't be attributed to anything
+pub(crate) const UNKNOWN: &str = "<unknown>";
+
+/// The number of code section bytes attributed to a crate, a source file or
+/// a function
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SizeEntry {
+    /// The name of the crate, the path of the source file or the name of the
+    /// function, or `<unknown>` for the code without debug info
+    pub name: String,
+    /// The number of bytes in the code section
+    pub size: u64,
+}
+
+/// The code section of the WASM broken down by crates, source files and
+/// functions (see [`crate::WASM::size_report`]), all of them sorted by size
+/// with the largest first
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SizeReport {
+    /// The total size of the function bodies in the code section
+    pub total: u64,
+    /// The code size per crate the code was compiled from
+    pub crates: Vec<SizeEntry>,
+    /// The code size per source file the code was compiled from
+    pub files: Vec<SizeEntry>,
+    /// The size of each function (body), with the functions of the same name
+    /// added up
+    pub functions: Vec<SizeEntry>,
+}
+
+impl SizeReport {
+    /// Serializes the report to JSON in the form of
+    /// `{"total":1234,"crates":[{"name":"core","size":567},...],"files":[...],"functions":[...]}`
+    pub fn to_json(&self) -> String {
+        let entries = |entries: &[SizeEntry]| {
+            entries
+                .iter()
+                .map(|entry| {
+                    format!(
+                        r#"{{"name":"{}","size":{}}}"#,
+                        json::encode(&entry.name),
+                        entry.size
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+
+        format!(
+            r#"{{"total":{},"crates":[{}],"files":[{}],"functions":[{}]}}"#,
+            self.total,
+            entries(&self.crates),
+            entries(&self.files),
+            entries(&self.functions)
+        )
+    }
+}
+
+// Adds up the sizes of the same names and sorts them by size
+#[derive(Default)]
+pub(crate) struct SizeTable(HashMap<String, u64>);
+
+impl SizeTable {
+    pub(crate) fn add(&mut self, name: &str, size: u64) {
+        if size == 0 {
+            return;
+        }
+
+        match self.0.get_mut(name) {
+            Some(total) => *total += size,
+            None => {
+                self.0.insert(name.to_owned(), size);
+            }
+        }
+    }
+
+    pub(crate) fn into_entries(self) -> Vec<SizeEntry> {
+        let mut entries = self
+            .0
+            .into_iter()
+            .map(|(name, size)| SizeEntry { name, size })
+            .collect::<Vec<_>>();
+        entries.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.name.cmp(&b.name)));
+        entries
+    }
+}
@@ -0,0 +1,176 @@
+use crate::error::Error;
+use std::{
+    borrow::Cow,
+    path::{Component, Path, PathBuf},
+    str::FromStr,
+};
+
+/// Where a source file referenced by the DWARF info comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum SourceKind {
+    /// A crate of the workspace the WASM was built from
+    Workspace,
+    /// A registry (i.e. crates.io) dependency under `CARGO_HOME/registry/src`
+    Registry,
+    /// A git dependency under `CARGO_HOME/git/checkouts`
+    Git,
+    /// The standard library (`std`, `core`, `alloc`, ...) under
+    /// `/rustc/<commit>/library`
+    Std,
+    /// Anything else, i.e. C sources or files outside of the workspace
+    Other,
+}
+
+impl FromStr for SourceKind {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "workspace" => Ok(Self::Workspace),
+            "registry" => Ok(Self::Registry),
+            "git" => Ok(Self::Git),
+            "std" => Ok(Self::Std),
+            "other" => Ok(Self::Other),
+            _ => Err(format!(
+                "Unknown source kind '{}', expected one of workspace, registry, git, std or other",
+                value
+            )
+            .into()),
+        }
+    }
+}
+
+/// A source file referenced by the DWARF info, classified by where it comes
+/// from (see [`crate::WASM::sources`])
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Source {
+    /// The path of the source file as recorded in the DWARF info
+    pub path: PathBuf,
+    /// Where the source file comes from
+    pub kind: SourceKind,
+    /// The name of the crate the source file belongs to, if known
+    pub crate_name: Option<String>,
+    /// The version of the crate for registry dependencies, the checked out
+    /// revision for git dependencies and the rustc commit hash for the
+    /// standard library
+    pub version: Option<String>,
+}
+
+impl Source {
+    /// Classifies the source file under 'path' from the usual places cargo
+    /// and rustup put the sources, with the files under 'workspace_root'
+    /// being the workspace crates:
+    /// * `/rustc/<commit>/library/<crate>/...` for the standard library
+    /// * `.../registry/src/<index>/<crate>-<version>/...` for registry crates
+    /// * `.../git/checkouts/<repository>-<hash>/<revision>/...` for git
+    ///   dependencies
+    ///
+    /// The crate of a workspace (or other) file is the directory containing
+    /// the `src` directory, which is the crate name for the usual layout.
+    /// The same goes for the crates in a git checkout, as a repository can
+    /// have several of them, except for `src` at the top of the checkout,
+    /// which is the crate named after the repository. Without a
+    /// 'workspace_root' the files with a crate name are considered to be in
+    /// the workspace.
+    pub fn classify(path: impl AsRef<Path>, workspace_root: Option<&Path>) -> Self {
+        let path = path.as_ref();
+        let components = path
+            .components()
+            .filter_map(|component| match component {
+                Component::Normal(name) => Some(name.to_string_lossy()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let position = |a: &str, b: &str| {
+            components
+                .windows(2)
+                .position(|window| window[0] == a && window[1] == b)
+        };
+        let source = |kind, crate_name: Option<&str>, version: Option<&str>| Self {
+            path: path.to_owned(),
+            kind,
+            crate_name: crate_name.map(str::to_owned),
+            version: version.map(str::to_owned),
+        };
+
+        let first = components.first().map(Cow::as_ref);
+        if first == Some("rustc") && components.get(2).map(Cow::as_ref) == Some("library") {
+            return source(
+                SourceKind::Std,
+                components.get(3).map(Cow::as_ref),
+                components.get(1).map(Cow::as_ref),
+            );
+        }
+        if let Some(index) = position("registry", "src") {
+            if let Some(package) = components.get(index + 3) {
+                let (name, version) = split_version(package);
+                return source(SourceKind::Registry, Some(name), version);
+            }
+        }
+        if let Some(index) = position("git", "checkouts") {
+            if let Some(checkout) = components.get(index + 2) {
+                let repository = checkout
+                    .rsplit_once('-')
+                    .map(|(name, _hash)| name)
+                    .unwrap_or(checkout);
+                let name = components
+                    .get(index + 4..)
+                    .and_then(crate_directory)
+                    .unwrap_or(repository);
+                let revision = components.get(index + 3).map(Cow::as_ref);
+                return source(SourceKind::Git, Some(name), revision);
+            }
+        }
+
+        let crate_name = crate_directory(&components);
+        let kind = match workspace_root {
+            Some(root) if path.starts_with(root) => SourceKind::Workspace,
+            None if crate_name.is_some() => SourceKind::Workspace,
+            _ => SourceKind::Other,
+        };
+        source(kind, crate_name, None)
+    }
+
+    /// Checks if the source file belongs to the crate called 'name', which
+    /// is the same regardless of using `-` or `_` in it
+    pub fn is_crate(&self, name: &str) -> bool {
+        let is_same = |a: u8, b: u8| a == b || (a == b'-' && b == b'_') || (a == b'_' && b == b'-');
+
+        self.crate_name
+            .as_deref()
+            .map(|crate_name| {
+                crate_name.len() == name.len()
+                    && crate_name
+                        .bytes()
+                        .zip(name.bytes())
+                        .all(|(a, b)| is_same(a, b))
+            })
+            .unwrap_or(false)
+    }
+}
+
+// The name of the directory containing the last `src` directory of the path
+// 'components', if any
+fn crate_directory<'a>(components: &'a [Cow<'_, str>]) -> Option<&'a str> {
+    components
+        .iter()
+        .rposition(|name| name == "src")
+        .and_then(|src| components.get(src.checked_sub(1)?))
+        .map(Cow::as_ref)
+}
+
+// Splits a registry crate directory to the crate name and the version, which
+// starts after the first `-` followed by a digit
+fn split_version(package: &str) -> (&str, Option<&str>) {
+    package
+        .match_indices('-')
+        .find(|(index, _)| {
+            package[index + 1..]
+                .chars()
+                .next()
+                .map(|char| char.is_ascii_digit())
+                .unwrap_or(false)
+        })
+        .map(|(index, _)| (&package[..index], Some(&package[index + 1..])))
+        .unwrap_or((package, None))
+}
@@ -0,0 +1,136 @@
+// Reader of the sourcemaps generated for WASM modules, to symbolicate the
+// offsets of a build when only its sourcemap is kept (see SymbolServer).
+//
+// The code of a WASM module is a single "line" of the sourcemap, so the
+// generated column of a segment is the offset in the module.
+use crate::{
+    debug_id,
+    error::Error,
+    json::{self, Value},
+    vlq, Location,
+};
+use std::{collections::BTreeMap, path::PathBuf};
+
+#[derive(Debug)]
+pub(crate) struct SourceMap {
+    // The source location of the code from each offset on, or None for the
+    // code which is mapped to nothing
+    segments: BTreeMap<u64, Option<Location>>,
+    debug_id: Option<String>,
+}
+
+impl SourceMap {
+    pub(crate) fn parse(text: &str) -> Result<Self, Error> {
+        let malformed = |what: &str| Error::from(format!("Malformed sourcemap, {}", what));
+        let sourcemap = json::decode(text)?;
+
+        let strings = |key: &str| -> Result<Vec<String>, Error> {
+            match sourcemap.get(key) {
+                Some(Value::Array(values)) => values
+                    .iter()
+                    .map(|value| match value {
+                        Value::String(value) => Ok(value.clone()),
+                        _ => Err(malformed(&format!("'{}' must be strings", key))),
+                    })
+                    .collect(),
+                None | Some(Value::Null) => Ok(Vec::new()),
+                Some(_) => Err(malformed(&format!("'{}' must be an array", key))),
+            }
+        };
+        let source_root = match sourcemap.get("sourceRoot") {
+            Some(Value::String(source_root)) => source_root.as_str(),
+            _ => "",
+        };
+        let sources = strings("sources")?
+            .iter()
+            .map(|source| PathBuf::from(format!("{}{}", source_root, source)))
+            .collect::<Vec<_>>();
+        let names = strings("names")?;
+        let mappings = match sourcemap.get("mappings") {
+            Some(Value::String(mappings)) => mappings,
+            _ => return Err(malformed("'mappings' must be a string")),
+        };
+
+        // The fields of the segments are relative to the previous segment
+        let mut segments = BTreeMap::new();
+        let mut fields = [0i64; 5];
+        for line in mappings.split(';') {
+            fields[0] = 0;
+            for segment in line.split(',').filter(|segment| !segment.is_empty()) {
+                let values = vlq::decode(segment)
+                    .filter(|values| matches!(values.len(), 1 | 4 | 5))
+                    .ok_or_else(|| malformed(&format!("invalid segment '{}'", segment)))?;
+                for (field, value) in fields.iter_mut().zip(&values) {
+                    *field = field
+                        .checked_add(*value)
+                        .ok_or_else(|| malformed(&format!("overflow in '{}'", segment)))?;
+                }
+
+                let offset = u64::try_from(fields[0])
+                    .map_err(|_| malformed(&format!("negative offset in '{}'", segment)))?;
+                let location = match values.len() {
+                    1 => None,
+                    _ => {
+                        let index = |value: i64| usize::try_from(value).ok();
+                        let path = index(fields[1])
+                            .and_then(|source| sources.get(source))
+                            .ok_or_else(|| {
+                                malformed(&format!("unknown source in '{}'", segment))
+                            })?;
+                        let position = |value: i64| {
+                            u64::try_from(value)
+                                .ok()
+                                .and_then(|value| value.checked_add(1))
+                                .ok_or(())
+                                .map_err(|_| {
+                                    malformed(&format!("negative position in '{}'", segment))
+                                })
+                        };
+                        Some(Location {
+                            function: match values.len() {
+                                5 => index(fields[4]).and_then(|name| names.get(name)).cloned(),
+                                _ => None,
+                            },
+                            path: path.clone(),
+                            line: position(fields[2])?,
+                            column: position(fields[3])?,
+                        })
+                    }
+                };
+                segments.insert(offset, location);
+            }
+        }
+
+        Ok(Self {
+            segments,
+            debug_id: match sourcemap.get("debugId") {
+                Some(Value::String(debug_id)) => Some(debug_id.clone()),
+                _ => None,
+            },
+        })
+    }
+
+    // The source location of the code at 'offset', the same way as
+    // WASM::lookup. The function is only known for the first segment of each
+    // function, where the sourcemap references its name.
+    pub(crate) fn lookup(&self, offset: u64) -> Option<Location> {
+        self.segments
+            .range(..=offset)
+            .next_back()
+            .and_then(|(_, location)| location.clone())
+    }
+
+    // Checks if 'debug_id' is the debugId of the sourcemap (see
+    // WASM::verify_debug_id)
+    pub(crate) fn verify_debug_id(&self, debug_id: &str) -> Result<(), Error> {
+        match &self.debug_id {
+            Some(expected) if debug_id::matches(debug_id, expected) => Ok(()),
+            Some(expected) => Err(format!(
+                "Debug ID mismatch, expected {} for the sourcemap, got {}",
+                expected, debug_id
+            )
+            .into()),
+            None => Err("The sourcemap has no debugId to verify".into()),
+        }
+    }
+}
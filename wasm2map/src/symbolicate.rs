@@ -0,0 +1,61 @@
+use std::{fmt, path::PathBuf};
+
+/// The source location the code at an offset of the WASM was compiled from
+/// (see [`crate::WASM::lookup`])
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    /// The qualified name of the function, if the DWARF info has it
+    pub function: Option<String>,
+    /// The path of the source file
+    pub path: PathBuf,
+    /// The line in the source file (1-based)
+    pub line: u64,
+    /// The column in the source file (1-based, 0 if not known)
+    pub column: u64,
+}
+
+impl fmt::Display for Location {
+    // Formats the location as `my_app::run (src/lib.rs:12:5)`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} ({}:{}",
+            self.function.as_deref().unwrap_or("<unknown>"),
+            self.path.display(),
+            self.line
+        )?;
+        if self.column != 0 {
+            write!(f, ":{}", self.column)?;
+        }
+        write!(f, ")")
+    }
+}
+
+// Finds the offset in the WASM module of the frame on a line of a stack
+// trace, in any of these formats:
+// * V8: `at wasm-function[42]:0x1a2b` or `at main (app.wasm:wasm-function[42]:0x1a2b)`
+// * SpiderMonkey: `main@http://localhost/app.wasm:wasm-function[42]:0x1a2b`
+// * wasmtime: `0:   0x1a2b - <unknown>!<wasm function 42>`
+pub(crate) fn frame_offset(line: &str) -> Option<u64> {
+    if let Some(start) = line.find("wasm-function[") {
+        let rest = &line[start..];
+        return parse_hex(&rest[rest.find("]:0x")? + 4..]);
+    }
+
+    let (index, rest) = line.trim_start().split_once(':')?;
+    if index.is_empty() || !index.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+    let rest = rest.trim_start().strip_prefix("0x")?;
+    let end = rest.find(" - ")?;
+
+    parse_hex(&rest[..end])
+}
+
+fn parse_hex(text: &str) -> Option<u64> {
+    let end = text
+        .find(|char: char| !char.is_ascii_hexdigit())
+        .unwrap_or(text.len());
+
+    u64::from_str_radix(&text[..end], 16).ok()
+}
@@ -0,0 +1,109 @@
+// Simple writer of POSIX (ustar) tar archives for the debug bundles, without
+// pulling in yet another dependency. Only regular files are written, with
+// fixed permissions, owner and modification time, so the same files always
+// give the same archive.
+
+use std::io::{self, Write};
+
+const BLOCK_SIZE: usize = 512;
+
+pub(crate) struct TarWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> TarWriter<W> {
+    pub(crate) fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    // Adds a regular file under 'name' (with `/` separators) to the archive
+    pub(crate) fn append(&mut self, name: &str, data: &[u8]) -> io::Result<()> {
+        let (prefix, short_name) = match split_name(name) {
+            Some(split) => split,
+            None => {
+                // Names which don't fit the ustar header are stored in a pax
+                // extended header preceding the file
+                let record = pax_record("path", name);
+                self.write_entry("", "PaxHeader", b'x', record.as_bytes())?;
+                ("", truncate(name, 100))
+            }
+        };
+
+        self.write_entry(prefix, short_name, b'0', data)
+    }
+
+    // Writes the two empty blocks marking the end of the archive and returns
+    // the underlying writer
+    pub(crate) fn finish(mut self) -> io::Result<W> {
+        self.writer.write_all(&[0; BLOCK_SIZE * 2])?;
+        self.writer.flush()?;
+
+        Ok(self.writer)
+    }
+
+    fn write_entry(&mut self, prefix: &str, name: &str, kind: u8, data: &[u8]) -> io::Result<()> {
+        let mut header = [0u8; BLOCK_SIZE];
+        header[..name.len()].copy_from_slice(name.as_bytes());
+        octal(&mut header[100..108], 0o644);
+        octal(&mut header[108..116], 0);
+        octal(&mut header[116..124], 0);
+        octal(&mut header[124..136], data.len() as u64);
+        octal(&mut header[136..148], 0);
+        header[156] = kind;
+        header[257..263].copy_from_slice(b"ustar\0");
+        header[263..265].copy_from_slice(b"00");
+        header[345..345 + prefix.len()].copy_from_slice(prefix.as_bytes());
+
+        // The checksum is calculated with the checksum field set to spaces
+        header[148..156].copy_from_slice(b"        ");
+        let checksum = header.iter().map(|&byte| u64::from(byte)).sum();
+        octal(&mut header[148..155], checksum);
+
+        self.writer.write_all(&header)?;
+        self.writer.write_all(data)?;
+        let padding = (BLOCK_SIZE - data.len() % BLOCK_SIZE) % BLOCK_SIZE;
+        self.writer.write_all(&[0; BLOCK_SIZE][..padding])
+    }
+}
+
+// Splits 'name' to the prefix (at most 155 bytes) and the name (at most 100
+// bytes) fields of the ustar header at a `/`, or returns None if it can't be
+fn split_name(name: &str) -> Option<(&str, &str)> {
+    if name.len() <= 100 {
+        return Some(("", name));
+    }
+
+    name.match_indices('/')
+        .map(|(index, _)| (&name[..index], &name[index + 1..]))
+        .find(|(prefix, name)| prefix.len() <= 155 && name.len() <= 100 && !name.is_empty())
+}
+
+// The longest prefix of 'name' of at most 'length' bytes on a character
+// boundary
+fn truncate(name: &str, length: usize) -> &str {
+    let mut end = length.min(name.len());
+    while !name.is_char_boundary(end) {
+        end -= 1;
+    }
+    &name[..end]
+}
+
+// Formats a pax extended header record, which starts with its own length
+fn pax_record(key: &str, value: &str) -> String {
+    // " key=value\n" plus the digits of the length, which are part of it
+    let rest = key.len() + value.len() + 3;
+    let mut length = rest + 1;
+    while length != rest + length.to_string().len() {
+        length = rest + length.to_string().len();
+    }
+
+    format!("{} {}={}\n", length, key, value)
+}
+
+// Writes 'value' as a zero padded, NUL terminated octal number filling the
+// 'field'
+fn octal(field: &mut [u8], value: u64) {
+    let digits = format!("{:0width$o}", value, width = field.len() - 1);
+    field[..digits.len()].copy_from_slice(digits.as_bytes());
+    field[digits.len()] = 0;
+}
@@ -1,13 +1,22 @@
-use std::{fs, ops::Deref, path::PathBuf};
+use std::{
+    borrow::Cow,
+    fs,
+    ops::Deref,
+    path::{Path, PathBuf},
+};
 
-use crate::{error::Error, json::encode, vlq, CodePoint, WASM};
+use crate::{
+    base64, column, compress, error::Error, filter, info, json::encode, md5, section, symbolicate,
+    tar, url, vlq, CodePoint, ColumnMode, Compression, GitRevision, LoadOptions, MapOptions, Row,
+    SizeEntry, Source, SourceEntry, SourceKind, SourceProvider, StaleSources, SymbolServer,
+    Transform, WASM,
+};
 
 // Consts needed to build golden versions of the binary WASM module section.
 // See wasm2map::WASM::patch() doc-comment for details.
 const WASM_CUSTOM_SECTION_ID: u8 = 0;
 const WASM_SOURCEMAPPINGURL_SECTION_NAME: &[u8] = b"sourceMappingURL";
 
-// TODO: Test sourcemap size load
 // TODO: Test sourcemap generation
 
 #[test]
@@ -39,6 +48,1213 @@ fn relative_paths_are_considered() {
     });
 }
 
+#[test]
+fn handles_dwarf5_file_tables() {
+    testutils::run_test_with_args(&["-Cdwarf-version=5"], |out| {
+        let mapper = WASM::load(out).expect("Failed to load DWARF 5 WASM");
+        let sourcemap = mapper.map_v3(true);
+
+        let source = format!(r#"/target/test{}.rs""#, testutils::get_thread_id());
+        assert!(sourcemap.contains(&source));
+        assert!(sourcemap.contains("fn main() {}"));
+    });
+}
+
+#[test]
+fn can_bundle_embedded_source() {
+    // A DWARF 5 compile unit for /src/m.rs, with the source embedded in the
+    // file table (DW_LNCT_LLVM_source) the way -Zembed-source emits it
+    let abbrev = testutils::custom(
+        ".debug_abbrev",
+        &[
+            1, 0x11, 0, // compile unit without children
+            0x03, 0x08, // name as an inline string
+            0x10, 0x17, // stmt_list as a section offset
+            0, 0, 0,
+        ],
+    );
+    let info = testutils::custom(
+        ".debug_info",
+        &[
+            &[18, 0, 0, 0, 5, 0][..], // length and version 5
+            &[1, 4, 0, 0, 0, 0],      // compile unit, address size, abbrev offset
+            &[1],
+            b"m.rs\0",
+            &[0, 0, 0, 0],
+        ]
+        .concat(),
+    );
+    let line = testutils::custom(
+        ".debug_line",
+        &[
+            &[80, 0, 0, 0, 5, 0, 4, 0, 55, 0, 0, 0][..], // length, version, header length
+            &[1, 1, 1, 0xfb, 14, 13],
+            &[0, 1, 1, 1, 1, 0, 0, 0, 1, 0, 0, 1], // standard opcode lengths
+            &[1, 0x01, 0x08, 1],                   // directories as inline strings
+            b"/src\0",
+            &[3, 0x01, 0x08, 0x02, 0x0f, 0x81, 0x40, 0x08, 1], // path, directory, source
+            b"m.rs\0\0fn main() {}\0",
+            &[0, 5, 2, 2, 0, 0, 0], // set address
+            &[4, 0, 5, 4, 1],       // file 0, column 4, copy
+            &[2, 1, 0, 1, 1],       // advance address, end sequence
+        ]
+        .concat(),
+    );
+    let module = testutils::module(&[
+        (1, &[1, 0x60, 0, 0]),
+        (3, &[1, 0]),
+        (10, &[1, 2, 0, 0x0b]),
+        (0, &abbrev),
+        (0, &info),
+        (0, &line),
+    ]);
+    testutils::run_test_with_module(&module, |out| {
+        let mapper = WASM::load(out).expect("Failed to load WASM");
+
+        // The source is not on the disk, but it's in the DWARF info
+        let contents = r#""sourcesContent":["fn main() {}"]"#;
+        assert!(mapper.map_v3(true).contains(contents));
+        let canonical = mapper
+            .map_v3_with(&MapOptions::new().bundle(true).canonical(true))
+            .expect("Failed to generate sourcemap");
+        assert!(canonical.contains(contents));
+        assert!(canonical.contains(r#""sources":["/src/m.rs"]"#));
+    });
+}
+
+#[test]
+fn stale_sources_are_detected_by_modification_time() {
+    testutils::run_test(|out| {
+        let mapper = WASM::load(out).expect("Failed to load WASM");
+
+        // Edit the source after the build
+        let source = testutils::source_path();
+        fs::write(&source, "fn main() { /* edited */ }").expect("Failed to edit source");
+        fs::File::options()
+            .write(true)
+            .open(&source)
+            .and_then(|file| {
+                file.set_modified(std::time::SystemTime::now() + std::time::Duration::from_secs(60))
+            })
+            .expect("Failed to touch source");
+
+        let options = |stale_sources| MapOptions {
+            bundle: true,
+            stale_sources,
+            ..Default::default()
+        };
+        assert!(mapper.map_v3_with(&options(StaleSources::Fail)).is_err());
+        let skipped = mapper
+            .map_v3_with(&options(StaleSources::Skip))
+            .expect("Failed to generate sourcemap");
+        assert!(!skipped.contains("edited"));
+        let warnings = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let reported = warnings.clone();
+        let warned = mapper
+            .map_v3_with(&options(StaleSources::Warn).on_stale_source(
+                move |path: &Path, _: &str| reported.lock().unwrap().push(path.to_owned()),
+            ))
+            .expect("Failed to generate sourcemap");
+        assert!(warned.contains("edited"));
+        assert_eq!(*warnings.lock().unwrap(), vec![source]);
+        let ignored = mapper
+            .map_v3_with(&options(StaleSources::Ignore))
+            .expect("Failed to generate sourcemap");
+        assert_eq!(ignored, warned);
+    });
+}
+
+#[test]
+fn stale_sources_are_detected_by_checksum() {
+    testutils::run_test(|out| {
+        let mut mapper = WASM::load(out).expect("Failed to load WASM");
+        let source = testutils::source_path();
+        let content = fs::read(&source).expect("Failed to read source");
+        let options = MapOptions {
+            bundle: true,
+            stale_sources: StaleSources::Fail,
+            ..Default::default()
+        };
+
+        // rustc doesn't emit DWARF 5 checksums, so fake the one in the table
+        let file = mapper
+            .files
+            .iter_mut()
+            .find(|(path, _)| path.ends_with(source.file_name().unwrap()))
+            .map(|(_, file)| file)
+            .expect("The test source is not in the source table");
+        file.md5 = Some(md5::digest(&content));
+        assert!(mapper.map_v3_with(&options).is_ok());
+
+        let file = mapper
+            .files
+            .iter_mut()
+            .find(|(path, _)| path.ends_with(source.file_name().unwrap()))
+            .map(|(_, file)| file)
+            .unwrap();
+        file.md5 = Some(md5::digest(b"fn main() { /* something else */ }"));
+        assert!(mapper.map_v3_with(&options).is_err());
+    });
+}
+
+#[test]
+fn sources_are_read_from_the_provider() {
+    struct Provided(PathBuf);
+
+    impl SourceProvider for Provided {
+        fn source(&self, path: &Path) -> Option<Cow<'_, str>> {
+            (path == self.0).then_some(Cow::Borrowed("fn main() { /* provided */ }"))
+        }
+    }
+
+    testutils::run_test(|out| {
+        let mapper = WASM::load(out).expect("Failed to load WASM");
+        let options = MapOptions::new()
+            .bundle(true)
+            .source_provider(Provided(testutils::source_path()));
+        let sourcemap = mapper
+            .map_v3_with(&options)
+            .expect("Failed to generate sourcemap");
+        assert!(sourcemap.contains("/* provided */"));
+        assert!(!mapper.map_v3(true).contains("/* provided */"));
+
+        // An explicit provider is used for canonical maps too
+        let sourcemap = mapper
+            .map_v3_with(&options.canonical(true))
+            .expect("Failed to generate sourcemap");
+        assert!(sourcemap.contains("/* provided */"));
+    });
+}
+
+#[test]
+fn can_bundle_sources_from_git() {
+    use std::process::Command;
+
+    let mut repository = testutils::get_target_dir();
+    repository.push("target");
+    repository.push(format!("git{}", testutils::get_thread_id()));
+    fs::create_dir_all(&repository).expect("Failed to create repository");
+    let git = |args: &[&str]| {
+        let status = Command::new("git")
+            .current_dir(&repository)
+            .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+            .args(args)
+            .status()
+            .expect("Failed to run git");
+        assert!(status.success());
+    };
+
+    let mut out = repository.clone();
+    out.push("test.wasm");
+    let out = out.display().to_string();
+    let source = format!("test{}.rs", testutils::get_thread_id());
+    testutils::build_in_dir(&repository, "fn main() { /* committed */ }", &out, &[]);
+    git(&["init", "-q"]);
+    git(&["add", &source]);
+    git(&["commit", "-q", "-m", "Build"]);
+    fs::write(repository.join(&source), "fn main() { /* edited */ }").expect("Failed to edit");
+
+    // A crate in a subdirectory of the repository, built with the paths
+    // remapped to be relative to it
+    let member = repository.join("member");
+    fs::create_dir_all(&member).expect("Failed to create member crate");
+    let remap = format!("--remap-path-prefix={}=.", member.display());
+    let member_out = member.join("test.wasm").display().to_string();
+    testutils::build_in_dir(
+        &member,
+        "fn main() { /* member */ }",
+        &member_out,
+        &[&remap],
+    );
+    git(&["add", &format!("member/{}", source)]);
+    git(&["commit", "-q", "-m", "Member"]);
+
+    let sources = GitRevision::new(&repository, "HEAD");
+    let unknown = GitRevision::new(&repository, "no-such-revision");
+    let commit = sources.as_ref().map(|sources| sources.commit().len()).ok();
+    let map = |out: &str, sources: Result<GitRevision, Error>| {
+        sources.and_then(|sources| {
+            let options = MapOptions::new()
+                .bundle(true)
+                .stale_sources(StaleSources::Ignore)
+                .source_provider(sources);
+            WASM::load(out).and_then(|mapper| mapper.map_v3_with(&options))
+        })
+    };
+    let sourcemap = map(&out, sources);
+    let member_sourcemap = map(&member_out, GitRevision::new(&member, "HEAD"));
+    fs::remove_dir_all(&repository).ok();
+
+    assert_eq!(commit, Some(40));
+    assert!(unknown.is_err());
+    assert!(sourcemap
+        .expect("Failed to generate sourcemap")
+        .contains("/* committed */"));
+    assert!(member_sourcemap
+        .expect("Failed to generate sourcemap")
+        .contains("/* member */"));
+}
+
+#[test]
+fn test_md5_digest() {
+    let hex = |digest: [u8; 16]| {
+        digest
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<String>()
+    };
+    assert_eq!(hex(md5::digest(b"")), "d41d8cd98f00b204e9800998ecf8427e");
+    assert_eq!(hex(md5::digest(b"abc")), "900150983cd24fb0d6963f7d28e17f72");
+    assert_eq!(
+        hex(md5::digest(
+            b"12345678901234567890123456789012345678901234567890123456789012345678901234567890"
+        )),
+        "57edf4a22be3c955ac49da2e2107b67a"
+    );
+}
+
+#[test]
+fn test_stale_sources_from_str() {
+    assert_eq!("skip".parse::<StaleSources>().unwrap(), StaleSources::Skip);
+    assert_eq!("fail".parse::<StaleSources>().unwrap(), StaleSources::Fail);
+    assert!("sometimes".parse::<StaleSources>().is_err());
+}
+
+#[test]
+fn test_columns_are_translated_to_utf16() {
+    let line = r#"    let _s = "éé🦀"; g()"#;
+
+    // The call to g() is at byte 26 and character 21 (1-based), but the crab
+    // takes two UTF-16 code units
+    assert_eq!(column::to_utf16(line, 26, ColumnMode::Bytes), 22);
+    assert_eq!(column::to_utf16(line, 21, ColumnMode::Chars), 22);
+    assert_eq!(column::to_utf16(line, 26, ColumnMode::Raw), 26);
+
+    // Columns before any multi-byte character don't move
+    assert_eq!(column::to_utf16(line, 5, ColumnMode::Bytes), 5);
+    assert_eq!(column::to_utf16(line, 5, ColumnMode::Chars), 5);
+
+    // A byte column in the middle of the crab points to the crab
+    assert_eq!(column::to_utf16(line, 20, ColumnMode::Bytes), 17);
+
+    // Past the end of the line and unknown columns
+    assert_eq!(column::to_utf16(line, 34, ColumnMode::Bytes), 30);
+    assert_eq!(column::to_utf16(line, 0, ColumnMode::Bytes), 0);
+
+    let lines = column::SourceLines::new("fn a() {}\r\n// é\n".into());
+    assert_eq!(lines.line(1), Some("fn a() {}"));
+    assert_eq!(lines.line(2), Some("// é"));
+    assert_eq!(lines.line(3), Some(""));
+    assert_eq!(lines.line(4), None);
+    assert_eq!(lines.line(0), None);
+
+    assert_eq!("chars".parse::<ColumnMode>().unwrap(), ColumnMode::Chars);
+    assert!("utf16".parse::<ColumnMode>().is_err());
+}
+
+#[test]
+fn test_source_paths_are_normalized_to_urls() {
+    let source_url = |path: &str| url::source_url(Path::new(path));
+
+    assert_eq!(source_url("/src/a:b.rs"), "/src/a:b.rs");
+    assert_eq!(source_url("src/lib.rs"), "src/lib.rs");
+    assert_eq!(source_url("a:b.rs"), "./a:b.rs");
+    assert_eq!(
+        source_url(r"C:\Users\me\lib.rs"),
+        "file:///C:/Users/me/lib.rs"
+    );
+    assert_eq!(source_url(r"\\?\C:\lib.rs"), "file:///C:/lib.rs");
+    assert_eq!(
+        source_url(r"\\server\share\lib.rs"),
+        "file://server/share/lib.rs"
+    );
+    assert_eq!(
+        source_url(r"\\?\UNC\server\share\lib.rs"),
+        "file://server/share/lib.rs"
+    );
+    assert_eq!(
+        source_url("C:/Users/me\\lib.rs"),
+        "file:///C:/Users/me/lib.rs"
+    );
+    assert_eq!(source_url(r"C:"), "file:///C:");
+    // Outside of Windows paths a backslash is part of the file name
+    assert_eq!(source_url(r"/src/a\b.rs"), "/src/a%5Cb.rs");
+    assert_eq!(source_url(r"src\lib.rs"), "src%5Clib.rs");
+    assert_eq!(source_url(r"C:lib\a.rs"), "./C:lib%5Ca.rs");
+    assert_eq!(source_url("file:///src/lib.rs"), "file:///src/lib.rs");
+    assert_eq!(
+        source_url("/my src/#1/100%/\"é\".rs"),
+        "/my%20src/%231/100%25/%22é%22.rs"
+    );
+
+    #[cfg(unix)]
+    {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+        let path = Path::new(OsStr::from_bytes(b"/src/\xFFa\xC3.rs"));
+        assert_eq!(url::source_url(path), "/src/%FFa%C3.rs");
+    }
+}
+
+#[test]
+fn sources_are_valid_json_strings() {
+    let mut target = testutils::get_target_dir();
+    target.push("target");
+    let remap = format!(r#"{}=we"ird\dir"#, target.display());
+
+    testutils::run_test_with_args(&["--remap-path-prefix", &remap], |out| {
+        let sourcemap = WASM::load(out).expect("Failed to load WASM").map_v3(false);
+
+        let source = format!(r#""we%22ird%5Cdir/test{}.rs""#, testutils::get_thread_id());
+        assert!(sourcemap.contains(&source), "{}", sourcemap);
+    });
+}
+
+#[test]
+fn canonical_maps_are_reproducible() {
+    let mut target = testutils::get_target_dir();
+    target.push("target");
+    let mut moved = target.clone();
+    moved.push(format!("moved{}", testutils::get_thread_id()));
+    fs::create_dir_all(&moved).expect("Failed to create build directory");
+
+    // The second build is remapped to the same directory in a roundabout
+    // way, which only the canonical maps resolve
+    let options = MapOptions::new().canonical(true);
+    let remap = |dir: &Path, to: &str| format!("{}={}", dir.display(), to);
+    let args = ["--remap-path-prefix"];
+
+    testutils::run_test_with_args(&[&args[..], &[&remap(&target, "/build")]].concat(), |out| {
+        let original = WASM::load(out)
+            .and_then(|mapper| mapper.map_v3_with(&options))
+            .expect("Failed to map WASM");
+
+        // Build the same source in another directory
+        let mut moved_out = moved.clone();
+        moved_out.push("test.wasm");
+        let moved_out = moved_out.display().to_string();
+        testutils::build_in_dir(
+            &moved,
+            "fn main() {}",
+            &moved_out,
+            &[&args[..], &[&remap(&moved, "/build/moved/..")]].concat(),
+        );
+        let rebuilt = WASM::load(&moved_out).and_then(|mapper| mapper.map_v3_with(&options));
+        let plain = WASM::load(&moved_out).map(|mapper| mapper.map_v3(false));
+        fs::remove_dir_all(&moved).ok();
+
+        assert_eq!(original, rebuilt.expect("Failed to map moved WASM"));
+        assert_ne!(original, plain.expect("Failed to map moved WASM"));
+        assert!(original.contains(&format!(
+            r#""/build/test{}.rs""#,
+            testutils::get_thread_id()
+        )));
+    });
+}
+
+#[test]
+fn test_normalize_source_paths() {
+    assert_eq!(url::normalize(Path::new("/a/b/../c")), Path::new("/a/c"));
+    assert_eq!(url::normalize(Path::new("/../a")), Path::new("/a"));
+    assert_eq!(url::normalize(Path::new("../a/./b/..")), Path::new("../a"));
+}
+
+#[test]
+fn excluded_sources_are_unmapped() {
+    testutils::run_test(|out| {
+        let mapper = WASM::load(out).expect("Failed to load WASM");
+        let source = format!("test{}.rs", testutils::get_thread_id());
+
+        let included = mapper
+            .map_v3_with(&MapOptions {
+                include: vec![format!("**/{}", source)],
+                ..Default::default()
+            })
+            .expect("Failed to generate sourcemap");
+        assert!(included.contains(&source));
+
+        let excluded = mapper
+            .map_v3_with(&MapOptions {
+                exclude: vec!["**/test*.rs".to_owned()],
+                ..Default::default()
+            })
+            .expect("Failed to generate sourcemap");
+        assert!(!excluded.contains(&source));
+
+        // The code of the excluded sources is still there, but in segments of
+        // a single value (A-Z and a-f are the VLQ digits ending a value)
+        let values = |segment: &str| {
+            segment
+                .chars()
+                .filter(|char| char.is_ascii_uppercase() || ('a'..='f').contains(char))
+                .count()
+        };
+        let mappings = excluded.rsplit_once(r#""mappings":""#).unwrap().1;
+        let mappings = mappings.split('"').next().unwrap();
+        assert!(!mappings.is_empty());
+        assert!(mappings.split(',').all(|segment| values(segment) == 1));
+    });
+}
+
+#[test]
+fn sourcemaps_are_configurable() {
+    use crate::json::{decode, Value};
+
+    testutils::run_test(|out| {
+        let mapper = WASM::load(out).expect("Failed to load WASM");
+        let map = |options: MapOptions| {
+            let sourcemap = mapper
+                .map_v3_with(&options)
+                .expect("Failed to generate sourcemap");
+            decode(&sourcemap).expect("Invalid sourcemap JSON")
+        };
+        let array = |sourcemap: &Value, key: &str| match sourcemap.get(key) {
+            Some(Value::Array(values)) => values.clone(),
+            other => panic!("Expected '{}' array, got {:?}", key, other),
+        };
+        let source = testutils::source_path();
+        let name = source.file_name().unwrap().to_str().unwrap().to_owned();
+
+        let plain = map(MapOptions::new());
+        assert_eq!(map(MapOptions::new().pretty(true)), plain);
+        assert_eq!(array(&plain, "names"), vec![]);
+        assert_eq!(plain.get("sourceRoot"), None);
+        assert_eq!(plain.get("ignoreList"), None);
+
+        let remapped = map(MapOptions::new()
+            .remap_path_prefix(source.parent().unwrap(), "/build")
+            .source_root("https://example.com/"));
+        assert!(array(&remapped, "sources").contains(&Value::String(format!("/build/{}", name))));
+        assert_eq!(
+            remapped.get("sourceRoot"),
+            Some(&Value::String("https://example.com/".to_owned()))
+        );
+
+        let ignored = map(MapOptions::new().ignore("**/test*.rs"));
+        let index = array(&ignored, "sources")
+            .iter()
+            .position(|url| matches!(url, Value::String(url) if url.ends_with(&name)))
+            .expect("Missing test source");
+        assert_eq!(
+            array(&ignored, "ignoreList"),
+            vec![Value::Number(index.to_string())]
+        );
+
+        // The first segment of each named function has the 5th name field
+        let named = map(MapOptions::new().names(true));
+        assert!(array(&named, "names")
+            .iter()
+            .any(|name| matches!(name, Value::String(name) if name.ends_with("::main"))));
+        let mappings = match named.get("mappings") {
+            Some(Value::String(mappings)) => mappings.clone(),
+            other => panic!("Expected mappings, got {:?}", other),
+        };
+        let values = |segment: &str| {
+            segment
+                .chars()
+                .filter(|char| char.is_ascii_uppercase() || ('a'..='f').contains(char))
+                .count()
+        };
+        assert!(mappings.split(',').any(|segment| values(segment) == 5));
+    });
+}
+
+#[test]
+fn mappings_can_be_transformed() {
+    use crate::json::{decode, Value};
+
+    struct Unchanged;
+    impl Transform for Unchanged {}
+
+    // Moves the sources to a CDN and drops the rest
+    struct Cdn(String);
+    impl Transform for Cdn {
+        fn source(&self, mut source: SourceEntry) -> Option<SourceEntry> {
+            source.url.ends_with(&self.0).then(|| {
+                source.url = format!("https://cdn.example.com/{}", self.0);
+                source
+            })
+        }
+    }
+
+    // Splits each row to two, the second one a byte later on the next line
+    struct Split;
+    impl Transform for Split {
+        fn row(&self, row: Row) -> Vec<Row> {
+            let next = Row {
+                address: row.address + 1,
+                line: row.line + 1,
+                ..row.clone()
+            };
+            vec![next, row]
+        }
+    }
+
+    // Drops the rows of the functions
+    struct DropFunctions;
+    impl Transform for DropFunctions {
+        fn row(&self, row: Row) -> Vec<Row> {
+            match row.function {
+                Some(_) => vec![],
+                None => vec![row],
+            }
+        }
+    }
+
+    testutils::run_test(|out| {
+        let mapper = WASM::load(out).expect("Failed to load WASM");
+        let map = |options: MapOptions| {
+            let sourcemap = mapper
+                .map_v3_with(&options)
+                .expect("Failed to generate sourcemap");
+            decode(&sourcemap).expect("Invalid sourcemap JSON")
+        };
+        let mappings = |sourcemap: &Value| match sourcemap.get("mappings") {
+            Some(Value::String(mappings)) => mappings.split(',').count(),
+            other => panic!("Expected mappings, got {:?}", other),
+        };
+        let name = format!("test{}.rs", testutils::get_thread_id());
+
+        let plain = map(MapOptions::new());
+        assert_eq!(map(MapOptions::new().transform(Unchanged)), plain);
+
+        let cdn = map(MapOptions::new().transform(Cdn(name.clone())));
+        assert_eq!(
+            cdn.get("sources"),
+            Some(&Value::Array(vec![Value::String(format!(
+                "https://cdn.example.com/{}",
+                name
+            ))]))
+        );
+
+        let split = map(MapOptions::new().transform(Split));
+        assert!(mappings(&split) > mappings(&plain));
+
+        // The transforms run in order, the dropped rows are unmapped
+        let dropped = map(MapOptions::new().transform(Split).transform(DropFunctions));
+        assert_eq!(dropped.get("sources"), Some(&Value::Array(vec![])));
+        assert!(mappings(&dropped) < mappings(&plain));
+    });
+}
+
+#[test]
+fn test_map_options() {
+    let options = MapOptions::new()
+        .bundle(true)
+        .remap_path_prefix("/home/me", "/src")
+        .remap_path_prefix("/home/me/app", "app")
+        .ignore_kind(SourceKind::Std);
+    assert!(options.bundle);
+    assert_eq!(options.ignore_kinds, vec![SourceKind::Std]);
+
+    // The last matching prefix wins, like with rustc
+    assert_eq!(
+        options.remap(Path::new("/home/me/app/src/lib.rs")),
+        Path::new("app/src/lib.rs")
+    );
+    assert_eq!(
+        options.remap(Path::new("/home/me/lib/src/lib.rs")),
+        Path::new("/src/lib/src/lib.rs")
+    );
+    assert_eq!(
+        options.remap(Path::new("/home/meh/lib.rs")),
+        Path::new("/home/meh/lib.rs")
+    );
+
+    let options = LoadOptions::new();
+    assert!(options.external_debug_info);
+    assert_eq!(options.debug_file, None);
+}
+
+#[test]
+fn test_source_filters() {
+    assert!(filter::glob_match("**/src/*.rs", "/home/me/app/src/lib.rs"));
+    assert!(filter::glob_match(
+        "**/app/**",
+        "/home/me/app/src/bin/main.rs"
+    ));
+    assert!(filter::glob_match("src/**/*.rs", "src/lib.rs"));
+    assert!(filter::glob_match("lib.r?", "lib.rs"));
+    assert!(!filter::glob_match("*.rs", "src/lib.rs"));
+    assert!(!filter::glob_match("src/?", "src/"));
+    assert!(filter::glob_match("***.rs", "src/lib.rs"));
+    assert!(filter::glob_match("**/", "/"));
+    assert!(!filter::glob_match("src/**/", "src/lib.rs"));
+
+    // Backtracking over the stars would never finish these
+    let text = "a".repeat(100);
+    assert!(!filter::glob_match(&format!("{}b", "*a".repeat(30)), &text));
+    assert!(!filter::glob_match(
+        &format!("{}b", "**a".repeat(30)),
+        &text
+    ));
+    assert!(filter::glob_match(&"*a".repeat(30), &text));
+
+    let options = MapOptions {
+        crates: vec!["my-app".to_owned()],
+        exclude_crates: vec!["core".to_owned()],
+        ..Default::default()
+    };
+    let is_included = |path: &str| filter::is_included(&Source::classify(path, None), &options);
+    assert!(is_included("/app/my_app/src/lib.rs"));
+    assert!(!is_included("/app/other/src/lib.rs"));
+    assert!(!is_included("/rustc/90b35a6/library/core/src/lib.rs"));
+
+    let options = MapOptions {
+        kinds: vec![SourceKind::Workspace],
+        ..Default::default()
+    };
+    let source = Source::classify("/app/my_app/src/lib.rs", Some(Path::new("/app")));
+    assert!(filter::is_included(&source, &options));
+    let source = Source::classify("/other/my_app/src/lib.rs", Some(Path::new("/app")));
+    assert!(!filter::is_included(&source, &options));
+}
+
+#[test]
+fn test_source_classification() {
+    let classify = |path: &str| {
+        let source = Source::classify(path, Some(Path::new("/home/me/app")));
+        (source.kind, source.crate_name, source.version)
+    };
+    let some = |value: &str| Some(value.to_owned());
+
+    assert_eq!(
+        classify("/rustc/90b35a6/library/core/src/panicking.rs"),
+        (SourceKind::Std, some("core"), some("90b35a6"))
+    );
+    assert_eq!(
+        classify("/home/me/.cargo/registry/src/index.crates.io-6f17d22bba15001f/wasm-bindgen-0.2.92/src/lib.rs"),
+        (SourceKind::Registry, some("wasm-bindgen"), some("0.2.92"))
+    );
+    assert_eq!(
+        classify("/home/me/.cargo/git/checkouts/gimli-5f3e2b1a9c8d7e6f/a1b2c3d/src/read/mod.rs"),
+        (SourceKind::Git, some("gimli"), some("a1b2c3d"))
+    );
+    // The crates of a multi-crate repository are in their own directories
+    assert_eq!(
+        classify("/home/me/.cargo/git/checkouts/wasm-bindgen-0a1b2c3d4e5f6a7b/9f8e7d6/crates/futures/src/lib.rs"),
+        (SourceKind::Git, some("futures"), some("9f8e7d6"))
+    );
+    assert_eq!(
+        classify("/home/me/.cargo/git/checkouts/wasm-bindgen-0a1b2c3d4e5f6a7b/9f8e7d6/build.rs"),
+        (SourceKind::Git, some("wasm-bindgen"), some("9f8e7d6"))
+    );
+    assert!(Source::classify(
+        "/home/me/.cargo/git/checkouts/wasm-bindgen-0a1b2c3d4e5f6a7b/9f8e7d6/crates/futures/src/lib.rs",
+        None
+    )
+    .is_crate("futures"));
+    assert_eq!(
+        classify("/home/me/app/crates/my_app/src/bin/main.rs"),
+        (SourceKind::Workspace, some("my_app"), None)
+    );
+    assert_eq!(classify("/tmp/main.rs"), (SourceKind::Other, None, None));
+
+    assert!(Source::classify("/app/my_app/src/lib.rs", None).is_crate("my-app"));
+    assert_eq!("git".parse::<SourceKind>().unwrap(), SourceKind::Git);
+    assert!("vendor".parse::<SourceKind>().is_err());
+}
+
+#[test]
+fn can_classify_sources() {
+    testutils::run_test(|out| {
+        let mapper = WASM::load(out).expect("Failed to load WASM");
+
+        // The test source is built from the target dir, like cargo builds
+        // the workspace crates from the workspace root
+        let source = mapper
+            .sources()
+            .into_iter()
+            .find(|source| source.path == testutils::source_path())
+            .expect("The test source is not in the source table");
+        assert_eq!(source.kind, SourceKind::Workspace);
+    });
+}
+
+#[test]
+fn can_report_code_size() {
+    testutils::run_test(|out| {
+        let report = WASM::load(out)
+            .and_then(|mapper| mapper.size_report())
+            .expect("Failed to create size report");
+        let sum = |entries: &[SizeEntry]| entries.iter().map(|entry| entry.size).sum::<u64>();
+
+        // Every byte of the function bodies is attributed to something
+        assert!(report.total > 0);
+        assert_eq!(sum(&report.crates), report.total);
+        assert_eq!(sum(&report.files), report.total);
+        assert_eq!(sum(&report.functions), report.total);
+
+        let source = testutils::source_path().display().to_string();
+        assert!(report.files.iter().any(|entry| entry.name == source));
+        assert!(report
+            .functions
+            .iter()
+            .any(|entry| entry.name.ends_with("::main")));
+        assert!(report.to_json().starts_with(r#"{"total":"#));
+    });
+}
+
+#[test]
+fn can_report_debug_info_quality() {
+    struct Provided(PathBuf);
+
+    impl SourceProvider for Provided {
+        fn source(&self, path: &Path) -> Option<Cow<'_, str>> {
+            (path == self.0).then_some(Cow::Borrowed("fn main() {}"))
+        }
+    }
+
+    testutils::run_test(|out| {
+        let mapper = WASM::load(out).expect("Failed to load WASM");
+        let source = testutils::source_path();
+
+        let report = mapper.quality_report().expect("Failed to create report");
+        assert!(report.covered > 0 && report.covered <= report.total);
+        assert_eq!(
+            report.functions.iter().map(|entry| entry.size).sum::<u64>(),
+            report.total
+        );
+        assert!(report.unresolved_sources.is_empty());
+        assert!(report.unreadable_sources.is_empty());
+        assert!(report.stale_sources.is_empty());
+
+        // The sources are checked the way the sourcemap would read them
+        let options = MapOptions::new().canonical(true);
+        let report = mapper
+            .quality_report_with(&options)
+            .expect("Failed to create report");
+        assert_eq!(report.unresolved_sources, vec![source.clone()]);
+
+        fs::write(&source, "fn main() {}\n// edited").expect("Failed to edit source");
+        let report = mapper.quality_report().expect("Failed to create report");
+        assert_eq!(report.stale_sources.len(), 1);
+        assert_eq!(report.stale_sources[0].0, source);
+        assert!(report.to_json().contains(r#""staleSources":[{"path":""#));
+
+        fs::write(&source, b"fn main() {} // \xff").expect("Failed to edit source");
+        let report = mapper.quality_report().expect("Failed to create report");
+        assert_eq!(report.unreadable_sources.len(), 1);
+        assert_eq!(report.unreadable_sources[0].0, source);
+
+        fs::remove_file(&source).expect("Failed to remove source");
+        let report = mapper.quality_report().expect("Failed to create report");
+        assert_eq!(report.unresolved_sources, vec![source.clone()]);
+        assert!(report.to_json().contains(r#""unresolvedSources":[""#));
+
+        let options = MapOptions::new().source_provider(Provided(source));
+        let report = mapper
+            .quality_report_with(&options)
+            .expect("Failed to create report");
+        assert!(report.unresolved_sources.is_empty());
+    });
+}
+
+#[test]
+fn can_symbolicate_stack_traces() {
+    testutils::run_test(|out| {
+        let mapper = WASM::load(out).expect("Failed to load WASM");
+        let (&start, main) = mapper
+            .functions
+            .iter()
+            .find(|(_, function)| function.name.ends_with("::main"))
+            .expect("Missing main function");
+        let (&offset, _) = mapper
+            .points
+            .range(start..main.end)
+            .find(|(_, point)| point.line != 0)
+            .expect("Missing line info for main");
+
+        let location = mapper.lookup(offset).expect("Failed to look up offset");
+        assert_eq!(location.path, testutils::source_path());
+        assert_eq!(location.function.as_deref(), Some(main.name.as_str()));
+        assert_eq!(mapper.lookup(u64::MAX), None);
+
+        let trace = format!(
+            "RuntimeError: unreachable\n    at wasm-function[1]:{0:#x}\nmain@http://localhost/test.wasm:wasm-function[1]:{0:#x}\n   0: {0:#8x} - <unknown>!<wasm function 1>\n    at wasm-function[1]:0xffffffff",
+            offset
+        );
+        let expected = format!(
+            "RuntimeError: unreachable\n    at {0}\nat {0}\n   at {0}\n    at wasm-function[1]:0xffffffff",
+            location
+        );
+        assert_eq!(mapper.symbolicate(&trace), expected);
+    });
+}
+
+#[test]
+fn test_stack_frame_offsets() {
+    assert_eq!(
+        symbolicate::frame_offset("    at wasm-function[42]:0x1a2b"),
+        Some(0x1a2b)
+    );
+    assert_eq!(
+        symbolicate::frame_offset(
+            "    at app.main (http://localhost/app.wasm:wasm-function[42]:0x1a2b)"
+        ),
+        Some(0x1a2b)
+    );
+    assert_eq!(
+        symbolicate::frame_offset("main@http://localhost/app.wasm:wasm-function[42]:0x1a2b"),
+        Some(0x1a2b)
+    );
+    assert_eq!(
+        symbolicate::frame_offset("  12:   0x1a2b - app.wasm!main"),
+        Some(0x1a2b)
+    );
+    assert_eq!(symbolicate::frame_offset("Error: 1: 0x12 failed"), None);
+    assert_eq!(symbolicate::frame_offset("    at main (app.js:12:5)"), None);
+}
+
+#[test]
+fn can_serve_symbols() {
+    use std::{
+        io::{Read, Write},
+        net::{Shutdown, TcpListener, TcpStream},
+    };
+
+    testutils::run_test(|out| {
+        let mapper = WASM::load(&out).expect("Failed to load WASM");
+        let (&offset, _) = mapper
+            .points
+            .iter()
+            .find(|(_, point)| point.line != 0)
+            .expect("Missing line info");
+        let location = mapper.lookup(offset).expect("Failed to look up offset");
+
+        let mut server = SymbolServer::new();
+        server.add("app-1.0.0", out);
+        let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind");
+        let address = listener.local_addr().expect("Missing local address");
+
+        // The requests are small enough to be buffered until the server
+        // gets to them on the same thread
+        let request = |request: &str| {
+            let mut client = TcpStream::connect(address).expect("Failed to connect");
+            client
+                .write_all(request.as_bytes())
+                .expect("Failed to send request");
+            client
+                .shutdown(Shutdown::Write)
+                .expect("Failed to end request");
+            let (stream, _) = listener.accept().expect("Failed to accept");
+            server.serve_connection(stream).expect("Failed to serve");
+
+            let mut response = String::new();
+            client
+                .read_to_string(&mut response)
+                .expect("Failed to read response");
+            response
+        };
+        let post = |body: &str| {
+            format!(
+                "POST /symbolicate HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\n\r\n{}",
+                body.len(),
+                body
+            )
+        };
+
+        let response = request(&post(&format!(
+            r#"{{"build":"app-1.0.0","offsets":[{}, "{:#x}", 18446744073709551615]}}"#,
+            offset, offset
+        )));
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        let frame = format!(
+            r#"{{"offset":{},"function":{},"file":"{}","line":{},"column":{}}}"#,
+            offset,
+            location
+                .function
+                .map(|function| format!(r#""{}""#, encode(&function)))
+                .unwrap_or_else(|| "null".to_owned()),
+            encode(&location.path.to_string_lossy()),
+            location.line,
+            location.column
+        );
+        assert!(response.ends_with(&format!(
+            r#"{{"build":"app-1.0.0","frames":[{},{},{{"offset":18446744073709551615}}]}}"#,
+            frame, frame
+        )));
+
+        let response = request(&post(r#"{"build":"app-2.0.0","offsets":[1]}"#));
+        assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+        assert!(response.ends_with(r#"{"error":"Unknown build 'app-2.0.0'"}"#));
+
+        let response = request(&post(&format!(
+            r#"{{"build":"app-1.0.0","debugId":"{}","offsets":[1]}}"#,
+            mapper.debug_id()
+        )));
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        let response = request(&post(
+            r#"{"build":"app-1.0.0","debugId":"00000000-0000-3000-8000-000000000000","offsets":[1]}"#,
+        ));
+        assert!(response.starts_with("HTTP/1.1 409 Conflict\r\n"));
+
+        let response = request(&post(r#"{"build":"app-1.0.0","offsets":[-1]}"#));
+        assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+        let response = request(&post(r#"{"build":"app-1.0.0","offsets":["#));
+        assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+        let response = request("POST /symbolicate HTTP/1.1\r\nContent-Length: 16000000\r\n\r\n{}");
+        assert!(response.ends_with(r#"{"error":"Unexpected end of the body"}"#));
+
+        let response = request("GET /builds HTTP/1.1\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with(r#"{"builds":["app-1.0.0"]}"#));
+
+        let response = request(&format!("GET /{} HTTP/1.1\r\n\r\n", "a".repeat(10_000)));
+        assert!(response.starts_with("HTTP/1.1 431 Request Header Fields Too Large\r\n"));
+        let response = request(&format!(
+            "GET /builds HTTP/1.1\r\n{}\r\n",
+            "A: b\r\n".repeat(200)
+        ));
+        assert!(response.starts_with("HTTP/1.1 431 Request Header Fields Too Large\r\n"));
+    });
+}
+
+#[test]
+fn can_serve_symbols_from_sourcemap() {
+    use std::{
+        io::{Read, Write},
+        net::{TcpListener, TcpStream},
+        thread,
+        time::Duration,
+    };
+
+    testutils::run_test(|out| {
+        let mapper = WASM::load(&out).expect("Failed to load WASM");
+        let (&offset, _) = mapper
+            .points
+            .iter()
+            .find(|(_, point)| point.line != 0)
+            .expect("Missing line info");
+        let location = mapper.lookup(offset).expect("Failed to look up offset");
+        let map_path = format!("{}.map", out);
+        fs::write(&map_path, mapper.map_v3(false)).expect("Failed to write sourcemap");
+
+        let mut server = SymbolServer::new();
+        server.add_map("app-1.0.0", &map_path);
+        let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind");
+        let address = listener.local_addr().expect("Missing local address");
+        thread::spawn(move || server.serve(&listener));
+
+        // A client which never sends its request doesn't hold up the rest
+        let mut idle = vec![TcpStream::connect(address).expect("Failed to connect")];
+
+        let body = format!(r#"{{"build":"app-1.0.0","offsets":[{}]}}"#, offset);
+        let mut client = TcpStream::connect(address).expect("Failed to connect");
+        client
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .expect("Failed to set timeout");
+        client
+            .write_all(
+                format!(
+                    "POST /symbolicate HTTP/1.1\r\nContent-Length: {}\r\n\r\n{}",
+                    body.len(),
+                    body
+                )
+                .as_bytes(),
+            )
+            .expect("Failed to send request");
+        let mut response = String::new();
+        client
+            .read_to_string(&mut response)
+            .expect("Failed to read response");
+
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with(&format!(
+            r#"{{"build":"app-1.0.0","frames":[{{"offset":{},"function":null,"file":"{}","line":{},"column":{}}}]}}"#,
+            offset,
+            encode(&location.path.to_string_lossy()),
+            location.line,
+            location.column
+        )));
+
+        // The connections over the limit are refused right away
+        while idle.len() < 64 {
+            idle.push(TcpStream::connect(address).expect("Failed to connect"));
+        }
+        let mut client = TcpStream::connect(address).expect("Failed to connect");
+        client
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .expect("Failed to set timeout");
+        let mut response = String::new();
+        client
+            .read_to_string(&mut response)
+            .expect("Failed to read response");
+        assert!(response.starts_with("HTTP/1.1 503 Service Unavailable\r\n"));
+    });
+}
+
+#[test]
+fn test_json_decode() {
+    use crate::json::{decode, Value};
+
+    assert_eq!(
+        decode(
+            r#" {"build" : "a\"\u00e9\ud83d\ude00", "offsets":[1, 2.5e3, -0, true, null, {}]} "#
+        )
+        .expect("Failed to decode JSON"),
+        Value::Object(vec![
+            ("build".to_owned(), Value::String("a\"é😀".to_owned())),
+            (
+                "offsets".to_owned(),
+                Value::Array(vec![
+                    Value::Number("1".to_owned()),
+                    Value::Number("2.5e3".to_owned()),
+                    Value::Number("-0".to_owned()),
+                    Value::Bool(true),
+                    Value::Null,
+                    Value::Object(vec![]),
+                ])
+            ),
+        ])
+    );
+    for invalid in [
+        "",
+        "[1,]",
+        "{\"a\"}",
+        "[1] 2",
+        "\"\\ud83d\"",
+        "\"\n\"",
+        "01.",
+        &"[".repeat(100),
+    ] {
+        assert!(decode(invalid).is_err(), "{}", invalid);
+    }
+}
+
+#[test]
+fn can_inspect_modules() {
+    testutils::run_test(|out| {
+        let mut mapper = WASM::load(&out).expect("Failed to load WASM");
+        let raw = fs::read(&out).expect("Cannot open the WASM file");
+
+        let sections = mapper.sections().expect("Malformed WASM");
+        let code = sections
+            .iter()
+            .find(|section| section.name == "code")
+            .expect("Missing code section");
+        assert_eq!(code.id, 10);
+        assert!(!code.is_custom());
+        assert!(sections
+            .iter()
+            .any(|section| section.is_custom() && section.name == ".debug_info"));
+        let last = sections.last().expect("Missing sections");
+        assert_eq!(last.offset + last.size, raw.len() as u64);
+
+        let units = mapper.compilation_units();
+        assert!(!units.is_empty());
+        assert!(units.iter().all(|unit| (2..=5).contains(&unit.version)
+            && unit.language.as_deref() == Some("DW_LANG_Rust")));
+        assert_eq!(mapper.debug_file(), None);
+
+        let producers = mapper.producers().expect("Malformed producers section");
+        assert!(producers
+            .iter()
+            .any(|producer| producer.field == "processed-by" && producer.name == "rustc"));
+
+        assert_eq!(mapper.source_mapping_url().expect("Malformed WASM"), None);
+        mapper
+            .patch("http://localhost:8080")
+            .expect("Failed to patch WASM");
+        let url = mapper.source_mapping_url().expect("Malformed WASM");
+        assert!(url
+            .expect("Missing sourceMappingURL")
+            .starts_with("http://localhost:8080"));
+    });
+}
+
+#[test]
+fn test_producers_section() {
+    let data = [
+        &[2, 8][..],
+        b"language",
+        &[1, 4],
+        b"Rust",
+        &[0],
+        &[12],
+        b"processed-by",
+        &[2, 5],
+        b"rustc",
+        &[6],
+        b"1.75.0",
+        &[12],
+        b"wasm-bindgen",
+        &[6],
+        b"0.2.92",
+    ]
+    .concat();
+    let producers = info::read_producers(&data).expect("Malformed producers section");
+    let producers = producers
+        .iter()
+        .map(|producer| {
+            (
+                producer.field.as_str(),
+                producer.name.as_str(),
+                producer.version.as_str(),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        producers,
+        vec![
+            ("language", "Rust", ""),
+            ("processed-by", "rustc", "1.75.0"),
+            ("processed-by", "wasm-bindgen", "0.2.92"),
+        ]
+    );
+
+    assert!(info::read_producers(&data[..data.len() - 1]).is_err());
+    assert!(info::read_producers(&[]).is_err());
+}
+
+#[test]
+fn test_function_sections() {
+    let module = testutils::module(&[
+        // An imported memory and function (env.f)
+        (2, b"\x02\x03env\x01m\x02\x00\x01\x03env\x01f\x00\x00"),
+        (10, &[2, 2, 0, 0x0b, 3, 0, 0x01, 0x0b]),
+        (0, b"\x04name\x01\x06\x01\x02\x03two"),
+    ]);
+    let sections = section::read(&module).expect("Malformed test WASM");
+
+    assert_eq!(
+        section::imported_functions(&module, &sections[0]).unwrap(),
+        1
+    );
+    let bodies = section::function_bodies(&module, &sections[1]).unwrap();
+    assert_eq!(
+        bodies.iter().map(|body| body.len()).collect::<Vec<_>>(),
+        vec![3, 4]
+    );
+    let names = section::function_names(&module, &sections[2]);
+    assert_eq!(names.get(&2).map(String::as_str), Some("two"));
+
+    // Imports cut off right after their kind, which skip past the end of the
+    // section: a tag (4) and a global (3) followed by another import
+    for imports in [&b"\x01\x01m\x01t\x04"[..], b"\x02\x01m\x01g\x03"] {
+        let module = testutils::module(&[(2, imports)]);
+        let sections = section::read(&module).expect("Malformed test WASM");
+        assert!(section::imported_functions(&module, &sections[0]).is_err());
+    }
+}
+
+#[test]
+fn compilation_dir_is_not_duplicated() {
+    let mut target = testutils::get_target_dir();
+    target.push("target");
+    let remap = format!("{}=remapped", target.display());
+
+    for version in ["-Cdwarf-version=4", "-Cdwarf-version=5"] {
+        testutils::run_test_with_args(&[version, "--remap-path-prefix", &remap], |out| {
+            let sourcemap = WASM::load(out).expect("Failed to load WASM").map_v3(false);
+
+            let source = format!(r#""remapped/test{}.rs""#, testutils::get_thread_id());
+            assert!(sourcemap.contains(&source), "{}", version);
+            assert!(!sourcemap.contains("remapped/remapped"), "{}", version);
+        });
+    }
+}
+
 #[test]
 fn can_bundle_source() {
     testutils::run_test(|out| {
@@ -199,9 +1415,627 @@ fn test_error_types() {
     assert_eq!(format!("{}", error), "This is a test");
 }
 
+#[test]
+fn test_vlq_encode() {
+    assert_eq!(vlq::encode(0), "A");
+    assert_eq!(vlq::encode(-1), "D");
+    assert_eq!(vlq::encode(16), "gB");
+    // The largest sourcemap position and the one past it
+    let max = i64::from(u32::MAX);
+    for value in [max, -max, max + 1, -max - 1] {
+        assert_eq!(vlq::decode(&vlq::encode(value)), Some(vec![value]));
+    }
+    // The sign bit needs one more bit than the value
+    assert_eq!(vlq::encode(i64::MAX).len(), 13);
+    assert_eq!(vlq::encode(i64::MIN).len(), 13);
+}
+
+#[test]
+fn positions_out_of_the_sourcemap_range_are_errors() {
+    // Moves all rows to the line and address
+    struct Move(u64, u64);
+    impl Transform for Move {
+        fn row(&self, row: Row) -> Vec<Row> {
+            vec![Row {
+                line: self.0,
+                address: self.1,
+                ..row
+            }]
+        }
+    }
+
+    testutils::run_test(|out| {
+        let mapper = WASM::load(out).expect("Failed to load WASM");
+        let max = u64::from(u32::MAX);
+        let map =
+            |line, address| mapper.map_v3_with(&MapOptions::new().transform(Move(line, address)));
+
+        assert!(map(max, max).is_ok());
+        assert!(map(max + 1, 0).is_err());
+        assert!(map(1, max + 1).is_err());
+        assert!(map(u64::MAX, 0).is_err());
+    });
+}
+
 #[test]
 fn test_numeric_encode_to_byte_sequence() {
-    assert_eq!(vlq::encode_uint_var(432), vec![176, 3])
+    assert_eq!(vlq::encode_uint_var(432), vec![176, 3]);
+    assert_eq!(
+        vlq::encode_uint_var(u64::MAX),
+        vec![255, 255, 255, 255, 255, 255, 255, 255, 255, 1]
+    );
+}
+
+#[test]
+fn test_numeric_decode_from_byte_sequence() {
+    assert_eq!(vlq::decode_uint_var(&[176, 3, 42]), Some((432, 2)));
+    // Padded encodings are valid LEB128
+    assert_eq!(vlq::decode_uint_var(&[128, 128, 128, 128, 0]), Some((0, 5)));
+    assert_eq!(
+        vlq::decode_uint_var(&vlq::encode_uint_var(u64::MAX)),
+        Some((u64::MAX, 10))
+    );
+    // Too large for u64
+    assert_eq!(
+        vlq::decode_uint_var(&[255, 255, 255, 255, 255, 255, 255, 255, 255, 2]),
+        None
+    );
+    // Truncated
+    assert_eq!(vlq::decode_uint_var(&[128]), None);
+}
+
+#[test]
+fn can_load_memory64_module() {
+    // A module with a single empty function and a 64 bit memory (flag 0x04)
+    let module = testutils::module(&[
+        (1, &[1, 0x60, 0, 0]),
+        (3, &[1, 0]),
+        (5, &[1, 0x04, 1]),
+        (10, &[1, 2, 0, 0x0b]),
+    ]);
+    testutils::run_test_with_module(&module, |out| {
+        let mapper = WASM::load(out).expect("Failed to load memory64 module");
+        assert!(mapper.map_v3(false).contains(r#""mappings":"","#));
+    });
+}
+
+#[test]
+fn can_map_memory64_module() {
+    // A compile unit for m.rs with 8 byte addresses
+    let abbrev = testutils::custom(
+        ".debug_abbrev",
+        &[
+            1, 0x11, 0, // compile unit without children
+            0x03, 0x08, // name as an inline string
+            0x10, 0x17, // stmt_list as a section offset
+            0, 0, 0,
+        ],
+    );
+    let info = testutils::custom(
+        ".debug_info",
+        &[
+            &[17, 0, 0, 0, 4, 0][..], // length and version 4
+            &[0, 0, 0, 0, 8],         // abbrev offset and address size
+            &[1],
+            b"m.rs\0",
+            &[0, 0, 0, 0],
+        ]
+        .concat(),
+    );
+    // Maps the `end` of the function (at offset 2 of the code section) to
+    // m.rs:3:5, setting the address with 8 bytes
+    let line = testutils::custom(
+        ".debug_line",
+        &[
+            &[55, 0, 0, 0, 4, 0, 28, 0, 0, 0][..], // length, version, header length
+            &[1, 1, 1, 0xfb, 14, 13],
+            &[0, 1, 1, 1, 1, 0, 0, 0, 1, 0, 0, 1], // standard opcode lengths
+            &[0],                                  // no include directories
+            b"m.rs\0\0\0\0\0",                     // the m.rs file
+            &[0, 9, 2, 2, 0, 0, 0, 0, 0, 0, 0],    // set address
+            &[3, 2, 5, 5, 1],                      // line 3, column 5, copy
+            &[2, 1, 0, 1, 1],                      // advance address, end sequence
+        ]
+        .concat(),
+    );
+    let module = testutils::module(&[
+        (1, &[1, 0x60, 0, 0]),
+        (3, &[1, 0]),
+        (5, &[1, 0x04, 1]),
+        (10, &[1, 2, 0, 0x0b]),
+        (0, &abbrev),
+        (0, &info),
+        (0, &line),
+    ]);
+    testutils::run_test_with_module(&module, |out| {
+        let mapper = WASM::load(out).expect("Failed to load memory64 module");
+        // The code section payload starts at offset 25 of the module
+        let location = mapper.lookup(27).expect("Missing line info");
+        assert_eq!(location.path, PathBuf::from("m.rs"));
+        assert_eq!((location.line, location.column), (3, 5));
+        assert_eq!(mapper.compilation_units()[0].name.as_deref(), Some("m.rs"));
+    });
+}
+
+#[test]
+fn can_replace_padded_sourcemap_section() {
+    const URL: &str = "http://localhost:8080";
+    let mut module =
+        testutils::module(&[(1, &[1, 0x60, 0, 0]), (3, &[1, 0]), (10, &[1, 2, 0, 0x0b])]);
+    let original_size = module.len();
+
+    // Append a sourceMappingURL section with its size padded to 5 bytes,
+    // the way some linkers emit it
+    let content = [
+        &[WASM_SOURCEMAPPINGURL_SECTION_NAME.len() as u8],
+        WASM_SOURCEMAPPINGURL_SECTION_NAME,
+        &[3],
+        b"foo",
+    ]
+    .concat();
+    module.push(WASM_CUSTOM_SECTION_ID);
+    module.extend_from_slice(&[0x80 | content.len() as u8, 0x80, 0x80, 0x80, 0]);
+    module.extend_from_slice(&content);
+
+    testutils::run_test_with_module(&module, |out| {
+        let mut mapper = WASM::load(&out).expect("Failed to load module");
+        mapper.patch(URL).expect("Failed to patch module");
+
+        let raw = fs::read(&out).expect("Cannot open the WASM file");
+        let section = [
+            &[WASM_CUSTOM_SECTION_ID] as &[u8],
+            &[(WASM_SOURCEMAPPINGURL_SECTION_NAME.len() + URL.len() + 2) as u8],
+            &[WASM_SOURCEMAPPINGURL_SECTION_NAME.len() as u8],
+            WASM_SOURCEMAPPINGURL_SECTION_NAME,
+            &[URL.len() as u8],
+            URL.as_bytes(),
+        ]
+        .concat();
+        assert_eq!(raw.len(), original_size + section.len());
+        assert_eq!(raw[original_size..], section);
+    });
+}
+
+#[test]
+fn can_load_dwarf_from_companion_file() {
+    testutils::run_test(|out| {
+        let expected = WASM::load(&out).expect("Failed to load WASM").map_v3(false);
+
+        // Move the DWARF to the companion file and strip it from the WASM
+        let debug = testutils::debug_companion_path();
+        fs::copy(&out, &debug).expect("Failed to copy WASM");
+        let raw = fs::read(&out).expect("Cannot open the WASM file");
+        fs::write(&out, testutils::strip_dwarf(&raw)).expect("Cannot write the WASM file");
+
+        let mapper = WASM::load_with_debug_info(&out, &debug).expect("Failed to load WASM");
+        assert_eq!(mapper.map_v3(false), expected);
+    });
+}
+
+#[test]
+fn can_follow_external_debug_info_section() {
+    testutils::run_test(|out| {
+        let expected = WASM::load(&out).expect("Failed to load WASM").map_v3(false);
+
+        let debug = testutils::debug_companion_path();
+        fs::copy(&out, &debug).expect("Failed to copy WASM");
+        let raw = fs::read(&out).expect("Cannot open the WASM file");
+        let name = debug.file_name().unwrap().to_str().unwrap();
+        let mut stripped = testutils::strip_dwarf(&raw);
+        stripped.extend_from_slice(&crate::section::encode_custom(
+            "external_debug_info",
+            &[&[name.len() as u8], name.as_bytes()].concat(),
+        ));
+        fs::write(&out, stripped).expect("Cannot write the WASM file");
+
+        let mapper = WASM::load(&out).expect("Failed to load WASM");
+        assert_eq!(mapper.map_v3(false), expected);
+
+        // Unless following the section is turned off
+        let mapper = WASM::load_with(&out, &LoadOptions::new().external_debug_info(false))
+            .expect("Failed to load WASM");
+        assert!(mapper.points.is_empty());
+
+        // Without the companion file the WASM is loaded without DWARF info
+        fs::remove_file(&debug).expect("Failed to remove companion file");
+        let mapper = WASM::load(&out).expect("Failed to load WASM");
+        assert!(mapper.points.is_empty());
+        assert_eq!(mapper.missing_debug_file(), Some(debug.as_path()));
+        assert_eq!(mapper.debug_file(), None);
+
+        // Unless it's given explicitly
+        assert!(WASM::load_with_debug_info(&out, &debug).is_err());
+    });
+}
+
+#[test]
+fn can_split_debug_info() {
+    testutils::run_test(|out| {
+        const URL: &str = "http://localhost:8080/test.wasm.map";
+        let mut mapper = WASM::load(&out).expect("Failed to load WASM");
+        let expected = mapper.map_v3(false);
+        mapper.patch(URL).expect("Failed to patch WASM");
+
+        let debug = testutils::debug_companion_path();
+        let name = debug.file_name().unwrap().to_str().unwrap().to_owned();
+        mapper
+            .split_debug_info(&debug, &name)
+            .expect("Failed to split debug info");
+
+        // The DWARF is moved to the companion, but the map still works
+        assert_eq!(mapper.map_v3(false), expected);
+        let raw = fs::read(&out).expect("Cannot open the WASM file");
+        let sections = crate::section::read(&raw).expect("Malformed WASM");
+        assert!(!sections.iter().any(|section| section.is_dwarf()));
+        assert!(sections
+            .iter()
+            .any(|section| section.name == Some("external_debug_info")));
+        assert_eq!(
+            sections.last().and_then(|section| section.name),
+            Some("sourceMappingURL")
+        );
+        let debug_raw = fs::read(&debug).expect("Cannot open the debug file");
+        let debug_sections = crate::section::read(&debug_raw).expect("Malformed debug file");
+        assert!(debug_sections.iter().any(|section| section.is_dwarf()));
+        assert!(object::File::parse(debug_raw.deref()).is_ok());
+
+        // The stripped WASM can be reloaded through its external_debug_info
+        let mut mapper = WASM::load(&out).expect("Failed to reload split WASM");
+        assert_eq!(mapper.map_v3(false), expected);
+        mapper.patch(URL).expect("Failed to patch split WASM");
+        assert!(object::File::parse(fs::read(&out).unwrap().deref()).is_ok());
+
+        // Nothing left to split
+        assert!(mapper.split_debug_info(&debug, &name).is_err());
+    });
+}
+
+#[test]
+fn maps_are_tied_to_the_wasm_by_debug_id() {
+    testutils::run_test(|out| {
+        const URL: &str = "http://localhost:8080/test.wasm.map";
+        let mut mapper = WASM::load(&out).expect("Failed to load WASM");
+        let debug_id = mapper.debug_id();
+        assert_eq!(debug_id.len(), 36);
+        assert_eq!(&debug_id[14..15], "3");
+
+        let sourcemap = mapper.map_v3(false);
+        assert!(sourcemap.ends_with(&format!(r#","debugId":"{}"}}"#, debug_id)));
+        assert!(mapper.verify_map(&sourcemap).is_ok());
+        assert!(mapper.verify_debug_id(&debug_id.to_uppercase()).is_ok());
+        assert!(mapper.verify_debug_id(&debug_id.replace('-', "")).is_ok());
+        assert!(mapper
+            .verify_debug_id("00000000-0000-3000-8000-000000000000")
+            .is_err());
+        assert!(mapper
+            .verify_map(&sourcemap.replace(&debug_id, "00000000-0000-3000-8000-000000000000"))
+            .is_err());
+        assert!(mapper
+            .verify_map(r#"{"version":3,"sources":[],"mappings":""}"#)
+            .is_err());
+
+        // The build_id section keeps the same debug ID
+        mapper.patch(URL).expect("Failed to patch WASM");
+        mapper.add_build_id().expect("Failed to add build id");
+        let size = fs::metadata(&out).expect("Missing WASM").len();
+        mapper.add_build_id().expect("Failed to add build id again");
+        assert_eq!(fs::metadata(&out).expect("Missing WASM").len(), size);
+
+        let raw = fs::read(&out).expect("Cannot open the WASM file");
+        let sections = section::read(&raw).expect("Malformed WASM");
+        assert_eq!(
+            sections
+                .iter()
+                .filter(|section| section.name == Some("build_id"))
+                .count(),
+            1
+        );
+        assert_eq!(
+            sections.last().and_then(|section| section.name),
+            Some("sourceMappingURL")
+        );
+        let mapper = WASM::load(&out).expect("Failed to reload WASM");
+        assert_eq!(mapper.debug_id(), debug_id);
+        assert!(mapper.verify_map(&sourcemap).is_ok());
+    });
+}
+
+#[test]
+fn can_load_compressed_modules() {
+    testutils::run_test(|out| {
+        let expected = WASM::load(&out).expect("Failed to load WASM").map_v3(false);
+        let raw = fs::read(&out).expect("Cannot open the WASM file");
+
+        let compressed = format!("{}.gz", out);
+        let data = Compression::Gzip
+            .compress(&raw)
+            .expect("Failed to compress WASM");
+        fs::write(&compressed, data).expect("Failed to write compressed WASM");
+        let result = WASM::load(&compressed);
+        fs::remove_file(&compressed).expect("Failed to remove compressed WASM");
+        let mut mapper = result.expect("Failed to load compressed WASM");
+        assert_eq!(mapper.map_v3(false), expected);
+
+        // The compressed file is never written
+        assert!(mapper.patch("http://localhost:8080/test.wasm.map").is_err());
+        assert!(mapper.strip_debug(false).is_err());
+        assert!(mapper.add_build_id().is_err());
+
+        // The compressed sourcemap decompresses to the plain one
+        let map = Compression::Gzip
+            .compress(expected.as_bytes())
+            .expect("Failed to compress sourcemap");
+        assert_eq!(
+            compress::decompress(Path::new("test.wasm.map.gz"), &map)
+                .expect("Failed to decompress sourcemap"),
+            Some(expected.into_bytes())
+        );
+        assert_eq!(
+            compress::decompress(Path::new("test.wasm.br"), &raw).expect("Not a WASM"),
+            None
+        );
+        assert!(compress::decompress(Path::new("test.wasm.br"), &[0x1b, 0x03]).is_err());
+    });
+}
+
+#[test]
+fn can_embed_sourcemaps() {
+    testutils::run_test(|out| {
+        let mut mapper = WASM::load(&out).expect("Failed to load WASM");
+        let sourcemap = mapper.map_v3(false);
+        assert_eq!(mapper.embedded_map().expect("Malformed WASM"), None);
+
+        mapper
+            .patch_inline(&sourcemap)
+            .expect("Failed to inline sourcemap");
+        assert_eq!(
+            mapper.embedded_map().expect("Malformed WASM"),
+            Some(sourcemap.clone())
+        );
+        let raw = fs::read(&out).expect("Cannot open the WASM file");
+        let sections = section::read(&raw).expect("Malformed WASM");
+        let last = sections.last().expect("Missing sections");
+        assert_eq!(last.name, Some("sourceMappingURL"));
+        let (url, _) = section::read_name(&raw, last.payload.start).expect("Malformed URL");
+        assert!(url.starts_with(b"data:application/json;base64,"));
+
+        // The custom section takes precedence and is replaced, not repeated
+        let bundled = mapper.map_v3(true);
+        mapper
+            .embed_map(&sourcemap)
+            .expect("Failed to embed sourcemap");
+        mapper
+            .embed_map(&bundled)
+            .expect("Failed to embed sourcemap");
+        assert_eq!(
+            mapper.embedded_map().expect("Malformed WASM"),
+            Some(bundled)
+        );
+        let raw = fs::read(&out).expect("Cannot open the WASM file");
+        let sections = section::read(&raw).expect("Malformed WASM");
+        assert_eq!(
+            sections
+                .iter()
+                .filter(|section| section.name == Some("sourceMap"))
+                .count(),
+            1
+        );
+        assert_eq!(
+            sections.last().and_then(|section| section.name),
+            Some("sourceMappingURL")
+        );
+
+        let mapper = WASM::load(&out).expect("Failed to reload WASM");
+        assert_eq!(mapper.map_v3(false), sourcemap);
+    });
+}
+
+#[test]
+fn test_data_urls() {
+    for (data, encoded) in [
+        ("", ""),
+        ("f", "Zg=="),
+        ("fo", "Zm8="),
+        ("foo", "Zm9v"),
+        ("foob", "Zm9vYg=="),
+        ("fooba", "Zm9vYmE="),
+        ("foobar", "Zm9vYmFy"),
+    ] {
+        assert_eq!(base64::encode(data.as_bytes()), encoded);
+        assert_eq!(base64::decode(encoded), Some(data.as_bytes().to_vec()));
+        assert_eq!(
+            base64::decode(encoded.trim_end_matches('=')),
+            Some(data.as_bytes().to_vec())
+        );
+    }
+    assert_eq!(base64::decode("Zm9vY"), None);
+    assert_eq!(base64::decode("Zm9v!"), None);
+
+    let sourcemap = r#"{"version":3,"sources":["ő.rs"]}"#;
+    assert_eq!(
+        url::parse_data_url(&url::data_url(sourcemap)).map(Result::unwrap),
+        Some(sourcemap.to_owned())
+    );
+    assert_eq!(
+        url::parse_data_url("data:application/json,%7B%22version%22%3A3%7D").map(Result::unwrap),
+        Some(r#"{"version":3}"#.to_owned())
+    );
+    assert!(url::parse_data_url("data:application/json,%7")
+        .unwrap()
+        .is_err());
+    assert!(url::parse_data_url("http://localhost/test.wasm.map").is_none());
+}
+
+#[test]
+fn can_bundle_debug_info() {
+    testutils::run_test(|out| {
+        let mapper = WASM::load(&out).expect("Failed to load WASM");
+        let mut archive = Vec::new();
+        mapper
+            .bundle(&mut archive, &MapOptions::default())
+            .expect("Failed to bundle debug info");
+        let entries = testutils::untar(&archive);
+
+        let name = Path::new(&out)
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .into_owned();
+        let source = testutils::source_path();
+        let file = format!("sources{}", source.display());
+        let names = entries
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names[..3],
+            ["manifest.json", name.as_str(), &format!("{}.map", name)]
+        );
+        assert!(names.contains(&file.as_str()));
+        assert!(names[3..].iter().all(|name| name.starts_with("sources/")));
+
+        let entry = |name: &str| {
+            entries
+                .iter()
+                .find(|(entry, _)| entry == name)
+                .map(|(_, data)| String::from_utf8_lossy(data).into_owned())
+                .expect("Missing archive entry")
+        };
+        let manifest = entry("manifest.json");
+        assert!(manifest.starts_with(&format!(
+            r#"{{"debugId":"{}","module":"{}","debugFile":null,"#,
+            mapper.debug_id(),
+            name
+        )));
+        assert!(manifest.contains(&format!(r#""file":"{}""#, file)));
+        assert_eq!(entry(&format!("{}.map", name)), mapper.map_v3(false));
+        assert_eq!(
+            entry(&file),
+            fs::read_to_string(&source).expect("Missing source")
+        );
+
+        // The separate DWARF info file goes to the archive too
+        let debug = testutils::debug_companion_path();
+        let debug_name = debug.file_name().unwrap().to_str().unwrap().to_owned();
+        let mut mapper = mapper;
+        mapper
+            .split_debug_info(&debug, &debug_name)
+            .expect("Failed to split debug info");
+        let mapper = WASM::load(&out).expect("Failed to reload split WASM");
+        let mut archive = Vec::new();
+        mapper
+            .bundle(&mut archive, &MapOptions::default())
+            .expect("Failed to bundle debug info");
+        let entries = testutils::untar(&archive);
+        assert_eq!(entries[2].0, debug_name);
+        assert_eq!(entries[2].1, fs::read(&debug).expect("Missing debug file"));
+        assert!(String::from_utf8_lossy(&entries[0].1)
+            .contains(&format!(r#""debugFile":"{}""#, debug_name)));
+    });
+}
+
+#[test]
+fn test_tar_archives() {
+    let long = format!("sources/{}/lib.rs", "a".repeat(120));
+    let longer = format!("sources/{}.rs", "b".repeat(300));
+    let mut tar = tar::TarWriter::new(Vec::new());
+    tar.append("manifest.json", b"{}").unwrap();
+    tar.append(&long, &[1; 512]).unwrap();
+    tar.append(&longer, &[2; 513]).unwrap();
+    let archive = tar.finish().unwrap();
+
+    assert_eq!(archive.len() % 512, 0);
+    assert_eq!(
+        testutils::untar(&archive),
+        [
+            ("manifest.json".to_owned(), b"{}".to_vec()),
+            (long, vec![1; 512]),
+            (longer, vec![2; 513]),
+        ]
+    );
+}
+
+#[test]
+fn test_archive_paths() {
+    assert_eq!(
+        url::archive_path("/home/me/app/src/lib.rs"),
+        "home/me/app/src/lib.rs"
+    );
+    assert_eq!(
+        url::archive_path("file:///C:/app/src/lib.rs"),
+        "C_/app/src/lib.rs"
+    );
+    assert_eq!(
+        url::archive_path("file://server/share/lib.rs"),
+        "server/share/lib.rs"
+    );
+    assert_eq!(url::archive_path("../../lib.rs"), "lib.rs");
+    assert_eq!(url::archive_path("./c:/lib.rs"), "c_/lib.rs");
+    assert_eq!(url::archive_path("/"), "unnamed");
+}
+
+#[test]
+fn can_strip_debug_info() {
+    testutils::run_test(|out| {
+        const URL: &str = "http://localhost:8080/test.wasm.map";
+        let mut mapper = WASM::load(&out).expect("Failed to load WASM");
+        let expected = mapper.map_v3(false);
+        mapper.patch(URL).expect("Failed to patch WASM");
+        let size = fs::metadata(&out).unwrap().len();
+
+        mapper.strip_debug(false).expect("Failed to strip WASM");
+        assert_eq!(mapper.map_v3(false), expected);
+        let raw = fs::read(&out).expect("Cannot open the WASM file");
+        assert!((raw.len() as u64) < size);
+        assert!(object::File::parse(raw.deref()).is_ok());
+        let sections = crate::section::read(&raw).expect("Malformed WASM");
+        assert!(!sections.iter().any(|section| section.is_dwarf()));
+        assert!(sections.iter().any(|section| section.name == Some("name")));
+        assert_eq!(
+            sections.last().and_then(|section| section.name),
+            Some("sourceMappingURL")
+        );
+
+        // The patch is kept and can still be updated in place
+        let section = testutils::peek_wasm_file_end(out.clone(), URL.len());
+        assert_eq!(section, URL.as_bytes());
+        mapper.strip_debug(true).expect("Failed to strip names");
+        mapper.patch(URL).expect("Failed to patch stripped WASM");
+        let raw = fs::read(&out).expect("Cannot open the WASM file");
+        let sections = crate::section::read(&raw).expect("Malformed WASM");
+        assert!(!sections.iter().any(|section| section.name == Some("name")));
+        assert_eq!(
+            sections
+                .iter()
+                .filter(|section| section.name == Some("sourceMappingURL"))
+                .count(),
+            1
+        );
+    });
+}
+
+#[test]
+fn test_mismatched_companion_file_is_an_error() {
+    testutils::run_test(|out| {
+        let debug = testutils::debug_companion_path();
+        fs::write(
+            &debug,
+            testutils::module(&[(1, &[1, 0x60, 0, 0]), (3, &[1, 0]), (10, &[1, 2, 0, 0x0b])]),
+        )
+        .expect("Failed to write companion file");
+
+        assert!(WASM::load_with_debug_info(&out, &debug).is_err());
+    });
+}
+
+#[test]
+fn test_malformed_section_size_is_an_error() {
+    let mut module = testutils::module(&[(1, &[1, 0x60, 0, 0])]);
+    // A custom section claiming to be larger than the file
+    module.extend_from_slice(&[WASM_CUSTOM_SECTION_ID, 0xff, 0xff, 0x03, 1, b'x']);
+    testutils::run_test_with_module(&module, |out| {
+        assert!(WASM::load(out).is_err());
+    });
 }
 
 #[test]
@@ -236,15 +2070,18 @@ fn test_json_encode() {
     let buf2 = &[36, 35, 34, 92, 93, 94];
     assert_eq!(
         encode(std::str::from_utf8(buf2.as_slice()).expect("Wrong second test buffer data")),
-        r#"$#\"\\"#
+        r#"$#\"\\]^"#
     );
+    assert_eq!(encode(r#"a"b"#), r#"a\"b"#);
+    assert_eq!(encode("fn main() {}\n}"), r"fn main() {}\n}");
+    assert_eq!(encode("plain"), "plain");
 }
 
 mod testutils {
     use std::{
         fs, panic,
-        path::PathBuf,
-        process::{Command, Stdio},
+        path::{Path, PathBuf},
+        process::Command,
     };
 
     // Get the target dir for the project or workspace directly from cargo
@@ -270,31 +2107,44 @@ mod testutils {
     // the created WASM binary to the project / workspace target dir.
     //
     // NOTE: We also force the WASM32 target obviously, so the tests need that toolchain
-    pub fn build_with_rustc(source: &'_ str, output: &'_ str) {
-        let mut file = get_target_dir();
-        file.push("target");
+    //
+    // The extra 'args' are passed to rustc, which is run from the target dir,
+    // so that's the compilation directory
+    pub fn build_with_rustc(source: &'_ str, output: &'_ str, args: &[&str]) {
+        let mut target = get_target_dir();
+        target.push("target");
+        build_in_dir(&target, source, output, args);
+    }
+
+    // Same as build_with_rustc, but the source is written to and compiled
+    // from the 'dir' directory
+    pub fn build_in_dir(dir: &Path, source: &'_ str, output: &'_ str, args: &[&str]) {
+        let mut file = dir.to_owned();
         file.push(format!("test{}.rs", get_thread_id()));
         std::fs::write(&file, source).unwrap();
 
-        let mut rustc = Command::new("rustc")
+        let rustc = Command::new("rustc")
+            .current_dir(dir)
             .args(["--target", "wasm32-unknown-unknown", "-g", "-o", output])
+            .args(args)
             .arg(file)
-            .stdout(Stdio::piped())
-            .spawn()
+            .output()
             .expect("Test WASM compile unsuccessful");
-        rustc
-            .wait()
-            .expect("Could not compile test WASM successfully");
+        assert!(
+            rustc.status.success(),
+            "Could not compile test WASM successfully:\n{}",
+            String::from_utf8_lossy(&rustc.stderr)
+        );
     }
 
     // Builds a test WASM file via rustc in the target directory for the tests
     // to manipulate
-    pub fn setup() -> String {
+    pub fn setup(args: &[&str]) -> String {
         let mut out = get_target_dir();
         out.push("target");
         out.push(format!("test{}.wasm", get_thread_id()));
 
-        build_with_rustc("fn main() {}", out.display().to_string().as_str());
+        build_with_rustc("fn main() {}", out.display().to_string().as_str(), args);
 
         out.to_string_lossy().to_string()
     }
@@ -311,6 +2161,8 @@ mod testutils {
         let mut input = target.clone();
         input.push(format!("test{}.rs", get_thread_id()));
         fs::remove_file(input.as_path()).ok();
+
+        fs::remove_file(debug_companion_path()).ok();
     }
 
     pub fn get_thread_id() -> u64 {
@@ -328,13 +2180,133 @@ mod testutils {
         binary[binary.len() - lookback..].to_owned()
     }
 
+    // The path of the source file the test WASM is built from
+    pub fn source_path() -> PathBuf {
+        let mut source = get_target_dir();
+        source.push("target");
+        source.push(format!("test{}.rs", get_thread_id()));
+        source
+    }
+
+    // The path of the separate DWARF companion file of the test WASM
+    pub fn debug_companion_path() -> PathBuf {
+        let mut debug = get_target_dir();
+        debug.push("target");
+        debug.push(format!("test{}.debug.wasm", get_thread_id()));
+        debug
+    }
+
+    // Reads the files of a tar archive, following the paths in the pax
+    // extended headers and checking the checksums
+    pub fn untar(mut archive: &[u8]) -> Vec<(String, Vec<u8>)> {
+        let field = |header: &[u8]| {
+            let end = header
+                .iter()
+                .position(|&byte| byte == 0)
+                .unwrap_or(header.len());
+            String::from_utf8(header[..end].to_vec()).expect("Malformed tar header")
+        };
+        let mut entries = Vec::new();
+        let mut long_name = None;
+
+        while !archive[..512].iter().all(|&byte| byte == 0) {
+            let header = &archive[..512];
+            let checksum = header[..148]
+                .iter()
+                .chain(b"        ")
+                .chain(&header[156..])
+                .map(|&byte| u64::from(byte))
+                .sum::<u64>();
+            assert_eq!(
+                u64::from_str_radix(&field(&header[148..156]), 8),
+                Ok(checksum)
+            );
+
+            let size = usize::from_str_radix(&field(&header[124..136]), 8).expect("Malformed size");
+            let data = archive[512..512 + size].to_vec();
+            let name = match field(&header[345..500]) {
+                prefix if prefix.is_empty() => field(&header[..100]),
+                prefix => format!("{}/{}", prefix, field(&header[..100])),
+            };
+            match header[156] {
+                b'x' => {
+                    let record = String::from_utf8(data).expect("Malformed pax header");
+                    let (length, rest) = record.split_once(' ').expect("Malformed pax record");
+                    assert_eq!(length.parse::<usize>(), Ok(record.len()));
+                    long_name = rest
+                        .strip_prefix("path=")
+                        .and_then(|path| path.strip_suffix('\n'))
+                        .map(str::to_owned);
+                }
+                b'0' => entries.push((long_name.take().unwrap_or(name), data)),
+                kind => panic!("Unexpected tar entry type {}", kind),
+            }
+            archive = &archive[512 + (size + 511) / 512 * 512..];
+        }
+
+        assert_eq!(archive, [0; 1024]);
+        entries
+    }
+
+    // Removes all DWARF custom sections from the WASM binary
+    pub fn strip_dwarf(raw: &[u8]) -> Vec<u8> {
+        let sections = crate::section::read(raw).expect("Malformed test WASM");
+        let mut stripped = raw[..8].to_vec();
+        for section in sections {
+            if !section.is_dwarf() {
+                stripped.extend_from_slice(&raw[section.range]);
+            }
+        }
+        stripped
+    }
+
+    // Encodes the content of a custom section with the name and data
+    pub fn custom(name: &str, data: &[u8]) -> Vec<u8> {
+        [&[name.len() as u8], name.as_bytes(), data].concat()
+    }
+
+    // Assembles a WASM module from (section id, section content) pairs
+    pub fn module(sections: &[(u8, &[u8])]) -> Vec<u8> {
+        let mut module = b"\0asm\x01\0\0\0".to_vec();
+        for (id, content) in sections {
+            module.push(*id);
+            module.push(content.len() as u8);
+            module.extend_from_slice(content);
+        }
+        module
+    }
+
+    // Run a test on a hand assembled WASM module instead of one built by rustc
+    pub fn run_test_with_module<T>(module: &[u8], test: T)
+    where
+        T: FnOnce(String),
+    {
+        let mut out = get_target_dir();
+        out.push("target");
+        out.push(format!("test{}.wasm", get_thread_id()));
+        fs::write(&out, module).expect("Failed to write the test WASM file");
+
+        let out = out.to_string_lossy().to_string();
+        let result = panic::catch_unwind(panic::AssertUnwindSafe(|| test(out)));
+        teardown();
+        assert!(result.is_ok())
+    }
+
     // Run a test with setup and teardown for the test case
     pub fn run_test<T>(test: T)
     where
-        T: FnOnce(String) + panic::UnwindSafe,
+        T: FnOnce(String),
+    {
+        run_test_with_args(&[], test)
+    }
+
+    // Run a test on a WASM built with extra rustc arguments
+    pub fn run_test_with_args<T>(args: &[&str], test: T)
+    where
+        T: FnOnce(String),
     {
-        let out = setup();
-        let result = panic::catch_unwind(|| test(out));
+        let out = setup(args);
+        let result = panic::catch_unwind(panic::AssertUnwindSafe(|| test(out)));
         teardown();
         assert!(result.is_ok())
     }
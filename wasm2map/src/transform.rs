@@ -0,0 +1,95 @@
+use std::{fmt, path::PathBuf};
+
+/// A row of the mappings: the code from 'address' up to the next row is
+/// compiled from 'line' and 'column' of the source file under 'path'
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Row {
+    /// The offset of the code in the code section of the WASM
+    pub address: u64,
+    /// The path of the source file, as it's recorded in the DWARF info
+    pub path: PathBuf,
+    /// The line in the source file (1-based)
+    pub line: u64,
+    /// The column in the source file (1-based, 0 if not known), as recorded
+    /// in the DWARF info before translating it (see [`crate::ColumnMode`])
+    pub column: u64,
+    /// The qualified name of the function the code is in, if the DWARF info
+    /// has it, which goes to the `names` of the sourcemap with
+    /// [`crate::MapOptions::names`]
+    pub function: Option<String>,
+}
+
+/// A source file of the sourcemap
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceEntry {
+    /// The path of the source file, as it's recorded in the DWARF info (and
+    /// in the [`Row`]s referencing it)
+    pub path: PathBuf,
+    /// The URL of the source file in the `sources` of the sourcemap
+    pub url: String,
+    /// The content of the source file in the `sourcesContent` of the
+    /// sourcemap, only read when bundling the sources (see
+    /// [`crate::MapOptions::bundle`])
+    pub content: Option<String>,
+    /// Whether the source file goes to the `ignoreList` of the sourcemap
+    pub ignored: bool,
+}
+
+/// Changes the mappings between reading the DWARF info and writing the
+/// sourcemap in project specific ways, i.e. to drop the frames of the panic
+/// machinery, attribute macro generated code to the macro invocation or
+/// rewrite the source URLs to the layout of a CDN.
+///
+/// The transforms set in [`crate::MapOptions::transforms`] run in order,
+/// each seeing the output of the previous one. The rows of the source files
+/// left out by the include/exclude filters are not passed to them.
+///
+/// ```rust
+/// use wasm2map::{MapOptions, Row, SourceEntry, Transform};
+///
+/// struct Cdn;
+///
+/// impl Transform for Cdn {
+///     // Drop the mappings of the panic machinery
+///     fn row(&self, row: Row) -> Vec<Row> {
+///         match row.function.as_deref() {
+///             Some(function) if function.starts_with("core::panicking::") => vec![],
+///             _ => vec![row],
+///         }
+///     }
+///
+///     fn source(&self, mut source: SourceEntry) -> Option<SourceEntry> {
+///         source.url = source
+///             .url
+///             .replacen("/home/me/myproject/", "https://cdn.example.com/", 1);
+///         Some(source)
+///     }
+/// }
+///
+/// let options = MapOptions::new().transform(Cdn);
+/// ```
+pub trait Transform: Send + Sync {
+    /// Edits, drops (returns no rows) or splits (returns several rows) a
+    /// row of the mappings. The code of a dropped row is mapped to nothing.
+    /// The rows returned can have any address, they're sorted afterwards.
+    ///
+    /// Returns the row unchanged by default.
+    fn row(&self, row: Row) -> Vec<Row> {
+        vec![row]
+    }
+
+    /// Edits or drops (returns None) a source file of the sourcemap. The
+    /// code of the rows referencing a dropped source file is mapped to
+    /// nothing.
+    ///
+    /// Returns the source file unchanged by default.
+    fn source(&self, source: SourceEntry) -> Option<SourceEntry> {
+        Some(source)
+    }
+}
+
+impl fmt::Debug for dyn Transform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Transform")
+    }
+}
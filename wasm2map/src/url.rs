@@ -0,0 +1,241 @@
+use crate::{base64, error::Error};
+use std::{
+    borrow::Cow,
+    path::{Component, Path, PathBuf},
+};
+
+// Turns the path of a source file into the URL in the `sources` table of the
+// sourcemap. Absolute and relative POSIX paths stay (root) relative to where
+// the sourcemap is served from, Windows paths with a drive letter or UNC
+// paths become `file://` URLs and paths which already are URLs are passed
+// through. The `\\` separators of the Windows paths are normalized to `/`
+// and everything which is not allowed in a URL path (including bytes which
+// are not valid UTF-8, and `\\` in the file names of other paths) is
+// percent-encoded, so no part of the original path is lost.
+pub(crate) fn source_url(path: &Path) -> String {
+    let bytes = path_bytes(path);
+
+    if is_url(&bytes) {
+        return String::from_utf8_lossy(&bytes).into_owned();
+    }
+
+    let bytes = match is_windows_path(&bytes) {
+        true => bytes
+            .iter()
+            .map(|&byte| if byte == b'\\' { b'/' } else { byte })
+            .collect::<Vec<_>>(),
+        false => bytes.into_owned(),
+    };
+
+    // Extended-length Windows paths (\\?\C:\... and \\?\UNC\server\share\...)
+    let bytes = match bytes.strip_prefix(b"//?/") {
+        Some(rest) => match rest.get(..4) {
+            Some(unc) if unc.eq_ignore_ascii_case(b"UNC/") => [b"//", &rest[4..]].concat(),
+            _ => rest.to_vec(),
+        },
+        None => bytes,
+    };
+
+    let (prefix, rest): (&str, &[u8]) = if has_drive_letter(&bytes) {
+        ("file:///", &bytes)
+    } else if bytes.starts_with(b"//") && !bytes.starts_with(b"///") {
+        ("file:", &bytes)
+    } else if !bytes.starts_with(b"/") && first_segment(&bytes).contains(&b':') {
+        // A colon in the first segment of a relative path would be read as
+        // the scheme of the URL
+        ("./", &bytes)
+    } else {
+        ("", &bytes)
+    };
+
+    let mut url = String::with_capacity(prefix.len() + rest.len());
+    url.push_str(prefix);
+    percent_encode(rest, &mut url);
+    url
+}
+
+// Resolves the `..` components of 'path' lexically, so the same file reached
+// through different directories has the same path. Leading `..` components of
+// a relative path are kept, as there's nothing to resolve them against.
+pub(crate) fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match normalized.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    normalized.pop();
+                }
+                Some(Component::RootDir | Component::Prefix(_)) => {}
+                _ => normalized.push(component),
+            },
+            _ => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+// Turns the URL of a source file in the sourcemap into a relative path in
+// the debug bundle archive, i.e. `/home/me/app/src/lib.rs` to
+// `home/me/app/src/lib.rs` and `file:///C:/app/src/lib.rs` to
+// `C_/app/src/lib.rs`, dropping the `.` and `..` segments
+pub(crate) fn archive_path(url: &str) -> String {
+    let path = url
+        .find("://")
+        .filter(|_| is_url(url.as_bytes()))
+        .map(|scheme| &url[scheme + 3..])
+        .unwrap_or(url);
+    let segments = path
+        .split('/')
+        .filter(|segment| !matches!(*segment, "" | "." | ".."))
+        .map(|segment| segment.replace(':', "_"))
+        .collect::<Vec<_>>();
+
+    match segments.is_empty() {
+        true => "unnamed".to_owned(),
+        false => segments.join("/"),
+    }
+}
+
+// Inlines the sourcemap JSON as a Base64 data URL, so the WASM doesn't need
+// a separate sourcemap file
+pub(crate) fn data_url(sourcemap: &str) -> String {
+    format!(
+        "data:application/json;base64,{}",
+        base64::encode(sourcemap.as_bytes())
+    )
+}
+
+// Reads back the sourcemap JSON inlined as a data URL (Base64 or percent
+// encoded), or returns None if 'url' is not a data URL
+pub(crate) fn parse_data_url(url: &str) -> Option<Result<String, Error>> {
+    let (media_type, data) = url.strip_prefix("data:")?.split_once(',')?;
+    let data = if media_type.ends_with(";base64") {
+        base64::decode(data)
+    } else {
+        percent_decode(data)
+    };
+
+    Some(
+        data.and_then(|data| String::from_utf8(data).ok())
+            .ok_or_else(|| "Malformed sourcemap data URL".into()),
+    )
+}
+
+fn percent_decode(text: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut rest = text.as_bytes();
+
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'%' {
+            let hex = tail
+                .get(..2)
+                .filter(|hex| hex.iter().all(u8::is_ascii_hexdigit))?;
+            bytes.push(u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok()?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+
+    Some(bytes)
+}
+
+#[cfg(unix)]
+fn path_bytes(path: &Path) -> Cow<'_, [u8]> {
+    use std::os::unix::ffi::OsStrExt;
+
+    Cow::Borrowed(path.as_os_str().as_bytes())
+}
+
+#[cfg(not(unix))]
+fn path_bytes(path: &Path) -> Cow<'_, [u8]> {
+    match path.to_string_lossy() {
+        Cow::Borrowed(path) => Cow::Borrowed(path.as_bytes()),
+        Cow::Owned(path) => Cow::Owned(path.into_bytes()),
+    }
+}
+
+// Checks for a URL scheme followed by `://`, like `file://` or `https://`
+fn is_url(bytes: &[u8]) -> bool {
+    let scheme = match bytes.windows(3).position(|window| window == b"://") {
+        Some(end) => &bytes[..end],
+        None => return false,
+    };
+
+    // Single letter schemes are Windows drive letters
+    scheme.len() > 1
+        && scheme[0].is_ascii_alphabetic()
+        && scheme
+            .iter()
+            .all(|&byte| byte.is_ascii_alphanumeric() || b"+-.".contains(&byte))
+}
+
+// Checks for a Windows path with a drive letter or a UNC (or extended-length)
+// prefix, the only paths where `\\` is a separator
+fn is_windows_path(bytes: &[u8]) -> bool {
+    bytes.starts_with(b"\\\\")
+        || matches!(bytes, [letter, b':', rest @ ..]
+            if letter.is_ascii_alphabetic() && matches!(rest.first(), None | Some(b'\\' | b'/')))
+}
+
+fn has_drive_letter(bytes: &[u8]) -> bool {
+    matches!(bytes, [letter, b':', rest @ ..]
+        if letter.is_ascii_alphabetic() && (rest.is_empty() || rest[0] == b'/'))
+}
+
+fn first_segment(bytes: &[u8]) -> &[u8] {
+    bytes.split(|&byte| byte == b'/').next().unwrap_or(bytes)
+}
+
+// Appends 'bytes' to 'url' with the characters of the URL path percent-encode
+// set (and `%` itself, and `\\`, which browsers read as `/`) escaped. Valid non-ASCII UTF-8 is kept as is, since
+// browsers take care of it, but invalid sequences are escaped byte by byte.
+fn percent_encode(mut bytes: &[u8], url: &mut String) {
+    const HEX_DIGITS: &[u8; 16] = b"0123456789ABCDEF";
+
+    let escape = |byte: u8, url: &mut String| {
+        url.push('%');
+        url.push(HEX_DIGITS[(byte >> 4) as usize] as char);
+        url.push(HEX_DIGITS[(byte & 0xF) as usize] as char);
+    };
+
+    while !bytes.is_empty() {
+        let (valid, invalid) = match std::str::from_utf8(bytes) {
+            Ok(valid) => (valid, &[][..]),
+            Err(error) => {
+                let (valid, rest) = bytes.split_at(error.valid_up_to());
+                let invalid_len = error.error_len().unwrap_or(rest.len());
+                (
+                    // The prefix was just validated, so this can't fail
+                    std::str::from_utf8(valid).unwrap_or_default(),
+                    &rest[..invalid_len],
+                )
+            }
+        };
+
+        for char in valid.chars() {
+            match char {
+                '\0'..='\x20'
+                | '\x7F'
+                | '"'
+                | '#'
+                | '%'
+                | '<'
+                | '>'
+                | '?'
+                | '\\'
+                | '`'
+                | '{'
+                | '}' => escape(char as u8, url),
+                _ => url.push(char),
+            }
+        }
+        for &byte in invalid {
+            escape(byte, url);
+        }
+
+        bytes = &bytes[valid.len() + invalid.len()..];
+    }
+}
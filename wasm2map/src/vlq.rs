@@ -5,27 +5,65 @@
 pub(crate) fn encode(value: i64) -> String {
     const VLQ_CHARS: &[u8] =
         "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/".as_bytes();
-    let mut x = if value >= 0 {
-        value << 1
-    } else {
-        (-value << 1) + 1
-    };
+    // The magnitude with the sign as the lowest bit, which needs one more
+    // bit than an i64 has
+    let mut x = u128::from(value.unsigned_abs()) << 1 | u128::from(value < 0);
     let mut result = String::new();
 
     while x > 31 {
-        let idx: usize = (32 + (x & 31)).try_into().unwrap();
+        let idx = 32 + (x & 31) as usize;
         let ch: char = VLQ_CHARS[idx].into();
         result.push(ch);
         x >>= 5;
     }
-    let idx: usize = x.try_into().unwrap();
+    let idx = x as usize;
     let ch: char = VLQ_CHARS[idx].into();
     result.push(ch);
 
     result
 }
 
-pub(crate) fn encode_uint_var(mut n: u32) -> Vec<u8> {
+// Decodes the Base64 VLQ values of a sourcemap segment (i.e. `kEAEoBA`),
+// or returns None if it's malformed
+pub(crate) fn decode(segment: &str) -> Option<Vec<i64>> {
+    let mut values = Vec::new();
+    let mut value: i64 = 0;
+    let mut shift = 0;
+
+    for char in segment.bytes() {
+        let digit = match char {
+            b'A'..=b'Z' => char - b'A',
+            b'a'..=b'z' => char - b'a' + 26,
+            b'0'..=b'9' => char - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return None,
+        };
+        if shift > 58 {
+            return None;
+        }
+        value |= i64::from(digit & 31) << shift;
+        shift += 5;
+
+        if digit & 32 == 0 {
+            values.push(match value & 1 {
+                0 => value >> 1,
+                _ => -(value >> 1),
+            });
+            value = 0;
+            shift = 0;
+        }
+    }
+
+    match shift {
+        0 => Some(values),
+        _ => None,
+    }
+}
+
+// Unsigned LEB128 encoding as used by the WebAssembly binary format for
+// section sizes, name lengths, etc.
+pub(crate) fn encode_uint_var(mut n: u64) -> Vec<u8> {
     let mut result = Vec::new();
     while n > 127 {
         result.push((128 | (n & 127)) as u8);
@@ -34,3 +72,24 @@ pub(crate) fn encode_uint_var(mut n: u32) -> Vec<u8> {
     result.push(n as u8);
     result
 }
+
+// Decodes an unsigned LEB128 number from the start of `bytes` and returns it
+// together with the number of bytes it occupied. Returns `None` if the input
+// ends before the number does, or if the encoded value does not fit in 64 bits.
+pub(crate) fn decode_uint_var(bytes: &[u8]) -> Option<(u64, usize)> {
+    let mut result: u64 = 0;
+    for (index, byte) in bytes.iter().enumerate() {
+        let shift = index * 7;
+        let value = u64::from(byte & 127);
+        if shift >= 64 || (shift == 63 && value > 1) {
+            return None;
+        }
+        result |= value << shift;
+
+        if byte & 128 == 0 {
+            return Some((result, index + 1));
+        }
+    }
+
+    None
+}